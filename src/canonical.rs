@@ -0,0 +1,225 @@
+//! Checking that data is in the canonical encoding produced by `to_canonical_bytes`.
+
+use crate::tag::Tag;
+
+/// Whether `bytes` hold exactly one value in its canonical encoding.
+///
+/// Numbers, lengths included, must use the smallest tag they fit in, NaNs must have the bit pattern of
+/// `f32::NAN` or `f64::NAN`, collections and strings must be written with their length and map entries
+/// must be sorted by the bytes of their encoded keys, without duplicates.
+pub fn is_canonical(bytes: &[u8]) -> bool {
+    let mut checker = Checker { bytes };
+    checker.value().is_some() && checker.bytes.is_empty()
+}
+
+/// Walk the tag stream, every function returns `None` when the data is malformed or not canonical.
+struct Checker<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Checker<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn tag(&mut self) -> Option<Tag> {
+        let byte = *self.take(1)?.first()?;
+        Tag::try_from(byte).ok()
+    }
+
+    fn unsigned(&mut self, len: usize) -> Option<u128> {
+        let bytes = self.take(len)?;
+        let value = bytes
+            .iter()
+            .fold(0u128, |acc, byte| (acc << 8) | u128::from(*byte));
+        // the value must not fit in the tag half as wide.
+        (len == 1 || value >> (len * 4) != 0).then_some(value)
+    }
+
+    fn signed(&mut self, len: usize) -> Option<()> {
+        let bytes = self.take(len)?;
+        let value = bytes
+            .iter()
+            .fold(0u128, |acc, byte| (acc << 8) | u128::from(*byte));
+        let shift = 128 - len * 8;
+        let value = ((value << shift) as i128) >> shift;
+        let half_bits = len * 4;
+        let fits_in_half = value >= -(1 << (half_bits - 1)) && value < (1 << (half_bits - 1));
+        (len == 1 || !fits_in_half).then_some(())
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let len = match self.tag()? {
+            Tag::U8 => self.unsigned(1)?,
+            Tag::U16 => self.unsigned(2)?,
+            Tag::U32 => self.unsigned(4)?,
+            Tag::U64 => self.unsigned(8)?,
+            _ => return None,
+        };
+        usize::try_from(len).ok()
+    }
+
+    fn values(&mut self, count: usize) -> Option<()> {
+        (0..count).try_for_each(|_| self.value())
+    }
+
+    fn value(&mut self) -> Option<()> {
+        match self.tag()? {
+            Tag::None | Tag::BoolFalse | Tag::BoolTrue | Tag::Unit | Tag::UnitStruct => Some(()),
+            Tag::Some | Tag::NewTypeStruct => self.value(),
+            Tag::I8 => self.signed(1),
+            Tag::I16 => self.signed(2),
+            Tag::I32 => self.signed(4),
+            Tag::I64 => self.signed(8),
+            #[cfg(not(no_integer128))]
+            Tag::I128 => self.signed(16),
+            Tag::U8 => self.unsigned(1).map(drop),
+            Tag::U16 => self.unsigned(2).map(drop),
+            Tag::U32 => self.unsigned(4).map(drop),
+            Tag::U64 => self.unsigned(8).map(drop),
+            #[cfg(not(no_integer128))]
+            Tag::U128 => self.unsigned(16).map(drop),
+            Tag::F32 => {
+                let value = f32::from_be_bytes(self.take(4)?.try_into().ok()?);
+                (!value.is_nan() || value.to_bits() == f32::NAN.to_bits()).then_some(())
+            }
+            Tag::F64 => {
+                let value = f64::from_be_bytes(self.take(8)?.try_into().ok()?);
+                (!value.is_nan() || value.to_bits() == f64::NAN.to_bits()).then_some(())
+            }
+            Tag::Char1 => self.take(1).map(drop),
+            Tag::Char2 => self.take(2).map(drop),
+            Tag::Char3 => self.take(3).map(drop),
+            Tag::Char4 => self.take(4).map(drop),
+            Tag::String => {
+                let len = self.len()?;
+                core::str::from_utf8(self.take(len)?).ok().map(drop)
+            }
+            Tag::Bytes => {
+                let len = self.len()?;
+                self.take(len).map(drop)
+            }
+            Tag::UnitVariant => self.value(),
+            Tag::NewTypeVariant => self.values(2),
+            Tag::TupleVariant => {
+                self.value()?;
+                let len = self.len()?;
+                self.values(len)
+            }
+            Tag::StructVariant => {
+                self.value()?;
+                let len = self.len()?;
+                self.values(len.checked_mul(2)?)
+            }
+            Tag::Seq | Tag::Tuple | Tag::TupleStruct => {
+                let len = self.len()?;
+                self.values(len)
+            }
+            Tag::Struct => {
+                let len = self.len()?;
+                self.values(len.checked_mul(2)?)
+            }
+            Tag::Map => {
+                let len = self.len()?;
+                let mut previous_key: Option<&[u8]> = None;
+                for _ in 0..len {
+                    let start = self.bytes;
+                    self.value()?;
+                    let key = &start[..start.len() - self.bytes.len()];
+                    if previous_key.is_some_and(|previous| previous >= key) {
+                        return None;
+                    }
+                    previous_key = Some(key);
+                    self.value()?;
+                }
+                Some(())
+            }
+            // names are written in full, as their index depends on the order of the buffered values.
+            Tag::TypeName => {
+                if self.tag()? != Tag::String {
+                    return None;
+                }
+                let len = self.len()?;
+                self.take(len)?;
+                self.value()
+            }
+            Tag::MarkerTerminatedString
+            | Tag::UnsizedSeq
+            | Tag::UnsizedSeqEnd
+            | Tag::UnsizedMap
+            | Tag::ChunkedString
+            | Tag::ChunkedBytes => None,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_canonical_map_and_nan() {
+        let mut first = HashMap::new();
+        let mut second = HashMap::new();
+        for i in 0..32u64 {
+            first.insert(i * 1000, f64::from_bits(0x7FF8_0000_0000_0000 | i));
+            second.insert(
+                (31 - i) * 1000,
+                f64::from_bits(0x7FF8_0000_0000_0000 | (31 - i)),
+            );
+        }
+        let first = crate::to_canonical_bytes(&first).unwrap();
+        let second = crate::to_canonical_bytes(&second).unwrap();
+        assert_eq!(first, second);
+        assert!(is_canonical(&first));
+
+        let decoded: HashMap<u64, f64> = crate::from_bytes(&first).unwrap();
+        assert_eq!(decoded.len(), 32);
+        assert!(decoded.values().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn test_not_canonical() {
+        // unsized sequence of one u8
+        assert!(!is_canonical(&[
+            Tag::UnsizedSeq as u8,
+            Tag::U8 as u8,
+            1,
+            Tag::UnsizedSeqEnd as u8
+        ]));
+        // u64 that fits in a u8
+        assert!(!is_canonical(&[Tag::U64 as u8, 0, 0, 0, 0, 0, 0, 0, 1]));
+        // -1 as an i16
+        assert!(!is_canonical(&[Tag::I16 as u8, 0xFF, 0xFF]));
+        assert!(is_canonical(&[Tag::I16 as u8, 0xFF, 0x00]));
+        // trailing bytes
+        assert!(!is_canonical(&[Tag::Unit as u8, Tag::Unit as u8]));
+
+        // chunks are joined in canonical mode
+        let mut bytes = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut bytes).with_canonical(true);
+        let mut chunks = serializer.serialize_chunked_str().unwrap();
+        chunks.write_chunk(b"hello ").unwrap();
+        chunks.write_chunk(b"world").unwrap();
+        chunks.finish().unwrap();
+        assert_eq!(bytes, crate::to_canonical_bytes("hello world").unwrap());
+
+        struct Pairs;
+        impl serde::Serialize for Pairs {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map([("a", 1), ("a", 2)])
+            }
+        }
+        assert!(matches!(
+            crate::to_canonical_bytes(&Pairs),
+            Err(crate::SerError::DuplicateKey)
+        ));
+    }
+}
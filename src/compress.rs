@@ -0,0 +1,248 @@
+//! Compressed rsbin data, prefixed by a header recording the codec.
+//!
+//! The header is `COMPRESSED_MAGIC` followed by the codec id, then comes the compressed encoding of the value.
+//! Each codec but `Codec::None` has its own feature.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+use crate::{de, error::EndOfBuff, read::BuffReader, ser, Serializer};
+
+/// First bytes of compressed data.
+pub const COMPRESSED_MAGIC: [u8; 4] = *b"rsbz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Stored as is, so the header can always be written.
+    None,
+    /// LZ4 frames, fast with a moderate ratio.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Deflate at a level from 0 to 10.
+    #[cfg(feature = "deflate")]
+    Deflate { level: u8 },
+    /// Zstandard at a level from 1 to 22, 0 being its default.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+impl Codec {
+    /// Id of the codec in the header.
+    pub fn id(&self) -> u8 {
+        match self {
+            Codec::None => 0,
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => 1,
+            #[cfg(feature = "deflate")]
+            Codec::Deflate { .. } => 2,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd { .. } => 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialize(ser::Error<io::Error>),
+    Deserialize(de::Error<EndOfBuff>),
+    /// The data doesn't start with `COMPRESSED_MAGIC`.
+    InvalidHeader,
+    /// The codec id is unknown or its feature is not enabled.
+    UnsupportedCodec(u8),
+    /// The data decompresses to more than this maximum len.
+    TooLarge(usize),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => Display::fmt(err, f),
+            Error::Serialize(err) => Display::fmt(err, f),
+            Error::Deserialize(err) => Display::fmt(err, f),
+            Error::InvalidHeader => f.write_str("Compressed data must start with \"rsbz\"."),
+            Error::UnsupportedCodec(id) => {
+                f.write_fmt(format_args!("Unsupported compression codec: {}", id))
+            }
+            Error::TooLarge(max_len) => f.write_fmt(format_args!(
+                "Compressed data decompresses to more than {} bytes.",
+                max_len
+            )),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<ser::Error<io::Error>> for Error {
+    fn from(value: ser::Error<io::Error>) -> Self {
+        Error::Serialize(value)
+    }
+}
+
+impl From<de::Error<EndOfBuff>> for Error {
+    fn from(value: de::Error<EndOfBuff>) -> Self {
+        Error::Deserialize(value)
+    }
+}
+
+/// Serialize `value` compressed with `codec` into `writer`, after a header recording the codec.
+pub fn to_writer_compressed<W, T>(value: &T, mut writer: W, codec: Codec) -> Result<(), Error>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    writer.write_all(&COMPRESSED_MAGIC)?;
+    writer.write_all(&[codec.id()])?;
+    match codec {
+        Codec::None => {
+            Serializer::to_writer(value, &mut writer)?;
+        }
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(&mut writer);
+            Serializer::to_writer(value, &mut encoder)?;
+            encoder.finish().map_err(io::Error::from)?;
+        }
+        #[cfg(feature = "deflate")]
+        Codec::Deflate { level } => {
+            let bytes = crate::to_bytes(value)?;
+            writer.write_all(&miniz_oxide::deflate::compress_to_vec(&bytes, level))?;
+        }
+        #[cfg(feature = "zstd")]
+        Codec::Zstd { level } => {
+            let mut encoder = zstd::Encoder::new(&mut writer, level)?;
+            Serializer::to_writer(value, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Deserialize a value written by `to_writer_compressed`, whatever its codec, failing if it decompresses
+/// to more than `max_len` bytes.
+pub fn from_reader_compressed<R, T>(reader: R, max_len: usize) -> Result<T, Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    Decompressed::from_reader(reader, max_len)?.deserialize()
+}
+
+/// Read `reader` to its end, failing if it yields more than `max_len` bytes.
+fn read_to_end_limited<R: Read>(reader: R, max_len: usize) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    reader.take(max_len as u64 + 1).read_to_end(&mut bytes)?;
+    if bytes.len() > max_len {
+        return Err(Error::TooLarge(max_len));
+    }
+    Ok(bytes)
+}
+
+/// Encoded data decompressed up front, values read from it borrow from its buffer.
+pub struct Decompressed {
+    bytes: Vec<u8>,
+}
+
+impl Decompressed {
+    /// Check the header written by `to_writer_compressed` then decompress the rest of `reader`,
+    /// failing if it decompresses to more than `max_len` bytes.
+    pub fn from_reader<R: Read>(mut reader: R, max_len: usize) -> Result<Self, Error> {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        let (magic, id) = header.split_at(4);
+        if magic != COMPRESSED_MAGIC {
+            return Err(Error::InvalidHeader);
+        }
+        let bytes = match id[0] {
+            0 => read_to_end_limited(reader, max_len)?,
+            #[cfg(feature = "lz4")]
+            1 => read_to_end_limited(lz4_flex::frame::FrameDecoder::new(reader), max_len)?,
+            #[cfg(feature = "deflate")]
+            2 => {
+                use miniz_oxide::inflate::{self, TINFLStatus};
+                let mut compressed = Vec::new();
+                reader.read_to_end(&mut compressed)?;
+                inflate::decompress_to_vec_with_limit(&compressed, max_len).map_err(
+                    |err| match err.status {
+                        TINFLStatus::HasMoreOutput => Error::TooLarge(max_len),
+                        _ => io::Error::new(io::ErrorKind::InvalidData, "Invalid deflate data.")
+                            .into(),
+                    },
+                )?
+            }
+            #[cfg(feature = "zstd")]
+            3 => read_to_end_limited(zstd::Decoder::new(reader)?, max_len)?,
+            id => return Err(Error::UnsupportedCodec(id)),
+        };
+        Ok(Decompressed { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Reader over the decompressed data, strings and bytes are borrowed from it.
+    pub fn reader(&self) -> BuffReader<'_> {
+        BuffReader::new(&self.bytes)
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>>(&'de self) -> Result<T, Error> {
+        Ok(crate::from_reader(self.reader())?)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn test_compression() {
+        let value: Vec<(u32, String)> = (0..500).map(|i| (i % 7, "repeated".into())).collect();
+        let plain = crate::to_bytes(&value).unwrap();
+        for codec in [
+            Codec::None,
+            Codec::Lz4,
+            Codec::Deflate { level: 6 },
+            Codec::Zstd { level: 3 },
+        ] {
+            let mut compressed = Vec::new();
+            to_writer_compressed(&value, &mut compressed, codec).unwrap();
+            assert_eq!(compressed[4], codec.id());
+            if codec != Codec::None {
+                assert!(compressed.len() * 5 < plain.len(), "{:?}", codec);
+            }
+            let read: Vec<(u32, String)> =
+                from_reader_compressed(&compressed[..], plain.len()).unwrap();
+            assert_eq!(read, value);
+
+            let decompressed = Decompressed::from_reader(&compressed[..], plain.len()).unwrap();
+            let borrowed: Vec<(u32, &str)> = decompressed.deserialize().unwrap();
+            assert_eq!(borrowed[3], (3, "repeated"));
+
+            assert!(matches!(
+                Decompressed::from_reader(&compressed[..], plain.len() - 1),
+                Err(Error::TooLarge(max_len)) if max_len == plain.len() - 1
+            ));
+        }
+
+        let unknown = *b"rsbz\x09";
+        assert!(matches!(
+            from_reader_compressed::<_, u8>(&unknown[..], 1),
+            Err(Error::UnsupportedCodec(9))
+        ));
+    }
+}
@@ -1,17 +1,45 @@
+#[cfg(feature = "std")]
+use crate::read::IoReader;
+#[cfg(feature = "alloc")]
+use crate::read::Recorder;
 use crate::{
-    error::{EndOfBuff, NoRWError, UnexpectedTag},
-    read::{BuffReader, Read},
-    tag::Tag,
+    error::{error_text, EndOfBuff, NoRWError, UnexpectedTag},
+    read::{BuffReader, Read, Reference},
+    stream::{STREAMED_BYTES, STREAM_CHUNK_SIZE},
+    tag::{Tag, UNSIZED_STRING_END_MARKER},
 };
-use serde::{de, serde_if_integer128};
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::cmp::Ordering;
+use serde::de;
 use serde::{de::Visitor, Deserialize};
 
 pub type Error<Re = NoRWError> = crate::error::DeError<Re>;
 pub type Result<T, Re = NoRWError> = core::result::Result<T, Error<Re>>;
 
+/// Deserializer of the rsbin format.
+///
+/// Type names written by `Serializer::with_type_names` are checked against the names of the structs,
+/// newtypes and enums being decoded. With the `alloc` feature, a name referring to the index of a name not
+/// read before is an error, so a part of data with type names only decodes if it writes its names in full.
+/// Names read without the `alloc` feature are not checked.
+///
+/// In strict mode, set by `with_strict`, the data must be written the one way canonical encoding writes it:
+/// numbers and lens use the smallest tag they fit in, sequences, tuples, tuple structs, maps and structs each
+/// use their own tag, tuples have the len of the type, strings, byte arrays, sequences and maps are written
+/// with their len and, with the `alloc` feature and a reader wrapped in a `Recorder` as `from_bytes_strict`
+/// does, map keys are unique and sorted by their encoding. `end` then checks no bytes are left.
 pub struct Deserializer<R> {
     reader: R,
     peeked_tag: Option<Tag>,
+    strict: bool,
+    // type names read so far, by index
+    #[cfg(feature = "alloc")]
+    type_names: Vec<String>,
+    // index of the type name written before the last popped tag
+    #[cfg(feature = "alloc")]
+    type_name: Option<usize>,
 }
 
 pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> core::result::Result<T, Error<EndOfBuff>>
@@ -21,6 +49,24 @@ where
     from_reader(BuffReader::new(bytes))
 }
 
+/// Deserialize `bytes` in strict mode, failing if bytes are left after the value.
+pub fn from_bytes_strict<'de, T>(bytes: &'de [u8]) -> core::result::Result<T, Error<EndOfBuff>>
+where
+    T: Deserialize<'de>,
+{
+    #[cfg(feature = "alloc")]
+    let reader = Recorder::new(BuffReader::new(bytes));
+    #[cfg(not(feature = "alloc"))]
+    let reader = BuffReader::new(bytes);
+    let mut de = Deserializer::new(reader).with_strict(true);
+    let value = T::deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+/// Deserialize from a `Read`, like a byte slice or an `IoReader`.
+///
+/// A `std::io::Read` isn't a `Read`, pass it to `from_io_reader` instead.
 pub fn from_reader<'de, T, R>(reader: R) -> core::result::Result<T, Error<R::Error>>
 where
     T: Deserialize<'de>,
@@ -30,6 +76,32 @@ where
     T::deserialize(&mut de)
 }
 
+/// Deserialize into `place`, reusing what it already allocated, like the capacity of its `Vec`s and `String`s.
+///
+/// On error `place` is left in a valid but unspecified state.
+pub fn from_bytes_in_place<'de, T>(
+    bytes: &'de [u8],
+    place: &mut T,
+) -> core::result::Result<(), Error<EndOfBuff>>
+where
+    T: Deserialize<'de>,
+{
+    let mut de = Deserializer::new(BuffReader::new(bytes));
+    T::deserialize_in_place(&mut de, place)
+}
+
+/// Deserialize from any `std::io::Read`, buffering it and reusing a scratch buffer for strings and bytes.
+///
+/// If the source is already a `BufRead` use `from_reader` with an `IoReader` to avoid double buffering.
+#[cfg(feature = "std")]
+pub fn from_io_reader<T, R>(reader: R) -> core::result::Result<T, Error<std::io::Error>>
+where
+    T: de::DeserializeOwned,
+    R: std::io::Read,
+{
+    from_reader(IoReader::new(std::io::BufReader::new(reader)))
+}
+
 macro_rules! match_tag {
     ($tag:expr, $($($pat:path)|+ => $body:expr),+) => {
         match $tag {
@@ -49,9 +121,15 @@ macro_rules! implement_number_parsing {
                 $expected_tag => {
                     self.pop_tag()?;
                     let bytes = self.pop_n()?;
+                    self.check_minimal($expected_tag, &bytes)?;
                     Ok($t::from_be_bytes(bytes))
                 },
-                $($tag => self.$small_fn().map($t::from)),+
+                $($tag => {
+                    if self.strict && $tag == Tag::F32 {
+                        return Err(Error::UnexpectedTag(UnexpectedTag { got: $tag, expected: &[$expected_tag] }));
+                    }
+                    self.$small_fn().map($t::from)
+                }),+
             }
         }
     };
@@ -60,6 +138,7 @@ macro_rules! implement_number_parsing {
             match_tag! { self.pop_tag()?,
                 $expected_tag => {
                     let bytes = self.pop_n()?;
+                    self.check_minimal($expected_tag, &bytes)?;
                     Ok($t::from_be_bytes(bytes))
                 }
             }
@@ -85,20 +164,133 @@ macro_rules! implement_number {
 }
 
 impl<'de, R: Read<'de>> Deserializer<R> {
-    fn new(reader: R) -> Self {
+    pub fn new(reader: R) -> Self {
         Deserializer {
             reader,
             peeked_tag: None,
+            strict: false,
+            #[cfg(feature = "alloc")]
+            type_names: Vec::new(),
+            #[cfg(feature = "alloc")]
+            type_name: None,
         }
     }
 
+    /// Only accept data written the way canonical encoding writes it.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// In strict mode, check a number of the integer tag `tag` doesn't fit in the tag half its size.
+    fn check_minimal(&self, tag: Tag, bytes: &[u8]) -> Result<(), R::Error> {
+        if !self.strict || bytes.len() < 2 {
+            return Ok(());
+        }
+        let (high, low) = bytes.split_at(bytes.len() / 2);
+        let fill = match tag {
+            Tag::U16 | Tag::U32 | Tag::U64 | Tag::U128 => 0,
+            Tag::I16 | Tag::I32 | Tag::I64 | Tag::I128 if low[0] & 0x80 == 0 => 0,
+            Tag::I16 | Tag::I32 | Tag::I64 | Tag::I128 => 0xFF,
+            _ => return Ok(()),
+        };
+        if high.iter().all(|byte| *byte == fill) {
+            return Err(Error::NonMinimal(tag));
+        }
+        Ok(())
+    }
+
+    /// In strict mode, check the next value has one of the `expected` tags.
+    fn check_strict_tag(&mut self, expected: &'static [Tag]) -> Result<(), R::Error> {
+        let got = self.peek_tag()?;
+        if self.strict && !expected.contains(&got) {
+            return Err(Error::UnexpectedTag(UnexpectedTag { got, expected }));
+        }
+        Ok(())
+    }
+
+    fn read_tag(&mut self) -> Result<Tag, R::Error> {
+        let byte = self.reader.read_byte()?;
+        byte.try_into().map_err(Error::TagParsingError)
+    }
+
     fn pop_tag(&mut self) -> Result<Tag, R::Error> {
         if let Some(tag) = self.peeked_tag.take() {
-            Ok(tag)
+            return Ok(tag);
+        }
+        let tag = self.read_tag()?;
+        if tag != Tag::TypeName {
+            #[cfg(feature = "alloc")]
+            {
+                self.type_name = None;
+            }
+            return Ok(tag);
+        }
+        #[cfg(feature = "alloc")]
+        {
+            self.type_name = Some(self.pop_type_name()?);
+        }
+        #[cfg(not(feature = "alloc"))]
+        self.pop_type_name()?;
+        self.read_tag()
+    }
+
+    /// Read the tag of a part of a type name with `read_tag`, so type names can't be nested, and peek it.
+    fn peek_type_name_tag(&mut self, expected: &'static [Tag]) -> Result<Tag, R::Error> {
+        let tag = self.read_tag()?;
+        if !expected.contains(&tag) {
+            return Err(Error::UnexpectedTag(UnexpectedTag { got: tag, expected }));
+        }
+        self.peeked_tag = Some(tag);
+        Ok(tag)
+    }
+
+    /// Read a type name, or the index of a previous one, giving back its index.
+    #[cfg(feature = "alloc")]
+    fn pop_type_name(&mut self) -> Result<usize, R::Error> {
+        if self.peek_type_name_tag(&[Tag::String, Tag::U8, Tag::U16, Tag::U32])? == Tag::String {
+            self.pop_tag()?;
+            self.peek_type_name_tag(&[Tag::U8, Tag::U16, Tag::U32, Tag::U64])?;
+            let len = self.pop_len()?;
+            let name = String::from(&*self.pop_str(len)?);
+            self.type_names.push(name);
+            return Ok(self.type_names.len() - 1);
+        }
+        let index = self.parse_u32()? as usize;
+        if index >= self.type_names.len() {
+            return Err(Error::UnknownTypeName(index));
+        }
+        Ok(index)
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn pop_type_name(&mut self) -> Result<(), R::Error> {
+        if self.peek_type_name_tag(&[Tag::String, Tag::U8, Tag::U16, Tag::U32])? == Tag::String {
+            self.pop_tag()?;
+            self.peek_type_name_tag(&[Tag::U8, Tag::U16, Tag::U32, Tag::U64])?;
+            let len = self.pop_len()?;
+            self.reader.read_bytes(len)?;
         } else {
-            let byte = self.reader.read_byte()?;
-            byte.try_into().map_err(Error::TagParsingError)
+            self.parse_u32()?;
+        }
+        Ok(())
+    }
+
+    /// Check the type name of the next value, if it has one, is `expected`.
+    fn check_type_name(&mut self, expected: &'static str) -> Result<(), R::Error> {
+        self.peek_tag()?;
+        #[cfg(feature = "alloc")]
+        if let Some(got) = self.type_name.map(|index| &self.type_names[index]) {
+            if !expected.is_empty() && got != expected {
+                return Err(Error::UnexpectedTypeName {
+                    expected,
+                    got: got.clone(),
+                });
+            }
         }
+        #[cfg(not(feature = "alloc"))]
+        let _ = expected;
+        Ok(())
     }
 
     fn peek_tag(&mut self) -> Result<Tag, R::Error> {
@@ -117,56 +309,52 @@ impl<'de, R: Read<'de>> Deserializer<R> {
         Ok(buff)
     }
 
-    #[cfg(feature = "alloc")]
-    fn convert_bytes_cow_to_str(
-        bytes: alloc::borrow::Cow<'_, [u8]>,
-    ) -> core::result::Result<alloc::borrow::Cow<'_, str>, core::str::Utf8Error> {
-        use alloc::borrow::Cow;
-        match bytes {
-            Cow::Borrowed(bytes) => core::str::from_utf8(bytes).map(Cow::Borrowed),
-            Cow::Owned(bytes) => alloc::string::String::from_utf8(bytes)
-                .map(Cow::Owned)
-                .map_err(|err| err.utf8_error()),
-        }
-    }
-
-    #[cfg(feature = "alloc")]
-    fn pop_str(&mut self, len: usize) -> Result<alloc::borrow::Cow<'de, str>, R::Error> {
+    fn pop_str(&mut self, len: usize) -> Result<Reference<'de, '_, str>, R::Error> {
         let bytes = self.reader.read_bytes(len)?;
-        Self::convert_bytes_cow_to_str(bytes).map_err(Error::Utf8Error)
+        bytes.to_str().map_err(Error::Utf8Error)
     }
 
-    #[cfg(not(feature = "alloc"))]
-    fn pop_str(&mut self, len: usize) -> Result<&'de str, R::Error> {
-        let bytes = self.reader.read_bytes(len)?;
-        core::str::from_utf8(bytes).map_err(Error::Utf8Error)
+    fn pop_unsized_str(&mut self) -> Result<Reference<'de, '_, str>, R::Error> {
+        let bytes = self.reader.read_bytes_until(&UNSIZED_STRING_END_MARKER)?;
+        // Read::read_bytes_until contract states it has to end with the 2 bytes of the marker, so bytes.len() >= 2
+        // this part remove the 2 bytes of the string end marker.
+        let bytes = bytes.map(|bytes| &bytes[..bytes.len() - 2]);
+        bytes.to_str().map_err(Error::Utf8Error)
     }
 
+    /// Read the chunks of a chunked string or bytes up to the end marker.
     #[cfg(feature = "alloc")]
-    fn pop_unsized_str(&mut self) -> Result<alloc::borrow::Cow<'de, str>, R::Error> {
+    fn pop_chunks(&mut self) -> Result<alloc::borrow::Cow<'de, [u8]>, R::Error> {
         use alloc::borrow::Cow;
-        let bytes = self.reader.read_bytes_until(crate::tag::end_of_str)?;
-        // Read::read_bytes_until contract states it has to end with the 2 bytes passed to the callback, so bytes.len() >= 2
-        let len = bytes.len() - 2;
-        // this part remove the 2 bytes of the string end marker.
-        let bytes = match bytes {
-            Cow::Owned(mut bytes) => {
-                bytes.truncate(len);
-                Cow::Owned(bytes)
+        let mut bytes = Cow::Borrowed(&[][..]);
+        while self.peek_tag()? != Tag::UnsizedSeqEnd {
+            let len = self.pop_len()?;
+            match (&mut bytes, self.reader.read_bytes(len)?) {
+                // a single borrowed chunk can be passed as is.
+                (Cow::Borrowed(bytes), Reference::Borrowed(chunk)) if bytes.is_empty() => {
+                    *bytes = chunk
+                }
+                (bytes, chunk) => bytes.to_mut().extend_from_slice(&chunk),
             }
-            Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[..len]),
-        };
-        Self::convert_bytes_cow_to_str(bytes).map_err(Error::Utf8Error)
+        }
+        self.pop_tag()?;
+        Ok(bytes)
     }
 
+    /// Without an allocator chunks can't be concatenated, only a single borrowed chunk is supported.
     #[cfg(not(feature = "alloc"))]
-    fn pop_unsized_str(&mut self) -> Result<&'de str, R::Error> {
-        let bytes = self.reader.read_bytes_until(crate::tag::end_of_str)?;
-        // Read::read_bytes_until contract states it has to end with the 2 bytes passed to the callback, so bytes.len() >= 2
-        let len = bytes.len() - 2;
-        // this part remove the 2 bytes of the string end marker.
-        let bytes = &bytes[..len];
-        core::str::from_utf8(bytes).map_err(Error::Utf8Error)
+    fn pop_chunks(&mut self) -> Result<&'de [u8], R::Error> {
+        let mut bytes: &'de [u8] = &[];
+        while self.peek_tag()? != Tag::UnsizedSeqEnd {
+            let len = self.pop_len()?;
+            match self.reader.read_bytes(len)? {
+                chunk if chunk.is_empty() => {}
+                Reference::Borrowed(chunk) if bytes.is_empty() => bytes = chunk,
+                _ => return Err(Error::ChunksWithoutAlloc),
+            }
+        }
+        self.pop_tag()?;
+        Ok(bytes)
     }
 
     fn pop_len(&mut self) -> Result<usize, R::Error> {
@@ -178,6 +366,48 @@ impl<'de, R: Read<'de>> Deserializer<R> {
         self.parse_u32()
     }
 
+    /// A variant is identified either by its index, its name, or a tuple of both.
+    /// When the name is present it is preferred, so variants can be reordered.
+    fn visit_variant_identifier<V>(&mut self, visitor: V) -> Result<V::Value, R::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match_tag! {
+            self.peek_tag()?,
+            Tag::U8 | Tag::U16 | Tag::U32 => {
+                let variant_index = self.pop_variant()?;
+                // serde's buffered identifiers only take u8 and u64 indexes.
+                visitor.visit_u64(variant_index.into())
+            },
+            Tag::String => self.visit_variant_name(visitor),
+            Tag::Tuple => {
+                self.pop_tag()?;
+                let len = self.pop_len()?;
+                if len != 2 {
+                    return Err(de::Error::invalid_length(len, &"a variant index and name"));
+                }
+                self.pop_variant()?;
+                self.visit_variant_name(visitor)
+            }
+        }
+    }
+
+    fn visit_variant_name<V>(&mut self, visitor: V) -> Result<V::Value, R::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match_tag! {
+            self.pop_tag()?,
+            Tag::String => {
+                let len = self.pop_len()?;
+                match self.pop_str(len)? {
+                    Reference::Borrowed(str) => visitor.visit_borrowed_str(str),
+                    Reference::Copied(str) => visitor.visit_str(str)
+                }
+            }
+        }
+    }
+
     implement_number_parsing!(
         parse_u64,
         u64,
@@ -210,37 +440,102 @@ impl<'de, R: Read<'de>> Deserializer<R> {
 
     implement_number_parsing!(parse_f64, f64, Tag::F64, parse_f32, f32, Tag::F32);
 
-    serde_if_integer128! {
-        implement_number_parsing!(
-            parse_u128,
-            u128,
-            Tag::U128,
-            Tag::U64,
-            parse_u64,
-            Tag::U32,
-            parse_u32,
-            Tag::U16,
-            parse_u16,
-            Tag::U8,
-            parse_u8
-        );
-        implement_number_parsing!(
-            parse_i128,
-            i128,
-            Tag::I128,
-            Tag::I64,
-            parse_i64,
-            Tag::I32,
-            parse_i32,
-            Tag::I16,
-            parse_i16,
-            Tag::I8,
-            parse_i8
-        );
+    implement_number_parsing!(
+        parse_u128,
+        u128,
+        Tag::U128,
+        Tag::U64,
+        parse_u64,
+        Tag::U32,
+        parse_u32,
+        Tag::U16,
+        parse_u16,
+        Tag::U8,
+        parse_u8
+    );
+    implement_number_parsing!(
+        parse_i128,
+        i128,
+        Tag::I128,
+        Tag::I64,
+        parse_i64,
+        Tag::I32,
+        parse_i32,
+        Tag::I16,
+        parse_i16,
+        Tag::I8,
+        parse_i8
+    );
+}
+
+impl<'de, R: Read<'de>> Deserializer<R> {
+    /// Check the reader reached its end, failing with `TrailingBytes` otherwise.
+    pub fn end(&mut self) -> Result<(), R::Error> {
+        if self.peeked_tag.is_some() || !self.reader.is_at_end()? {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(())
+    }
+
+    /// Visit a sequence with any of the sequence tags, checking in strict mode it has `expected_len` elements.
+    fn visit_seq<V>(
+        &mut self,
+        expected_len: Option<usize>,
+        visitor: V,
+    ) -> Result<V::Value, R::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let len = match_tag! {
+            self.pop_tag()?,
+            Tag::Seq | Tag::Tuple | Tag::TupleStruct => {
+                self.pop_len().map(Some)
+            },
+            Tag::UnsizedSeq => {
+                if self.strict {
+                    return Err(Error::UnexpectedTag(UnexpectedTag { got: Tag::UnsizedSeq, expected: &[Tag::Seq] }));
+                }
+                Ok(None)
+            }
+        }?;
+        if let (true, Some(expected), Some(len)) = (self.strict, expected_len, len) {
+            if len != expected {
+                return Err(Error::InvalidLength {
+                    len,
+                    expected: error_text(format_args!("{} elements", expected)),
+                });
+            }
+        }
+
+        visitor.visit_seq(SeqDeserializer::new(self, len))
+    }
+
+    /// Visit a map with any of the map tags, checking in strict mode the keys of a map are sorted.
+    fn visit_map<V>(&mut self, visitor: V) -> Result<V::Value, R::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let tag = self.pop_tag()?;
+        let len = match_tag! {
+            tag,
+            Tag::Map | Tag::Struct => {
+                self.pop_len().map(Some)
+            },
+            Tag::UnsizedMap => {
+                if self.strict {
+                    return Err(Error::UnexpectedTag(UnexpectedTag { got: Tag::UnsizedMap, expected: &[Tag::Map] }));
+                }
+                Ok(None)
+            }
+        }?;
+
+        // struct fields stay in the order of the struct in canonical encoding
+        let sorted_keys = self.strict && tag == Tag::Map;
+        visitor.visit_map(SeqDeserializer::map(self, len, sorted_keys))
     }
 }
 
-impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
+impl<'de, R: Read<'de>> de::Deserializer<'de> for &mut Deserializer<R> {
     type Error = Error<R::Error>;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, R::Error>
@@ -261,23 +556,29 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
             Tag::F32 => self.deserialize_f32(visitor),
             Tag::F64 => self.deserialize_f64(visitor),
             Tag::Char1 | Tag::Char2 | Tag::Char3 | Tag::Char4 => self.deserialize_char(visitor),
-            Tag::String | Tag::MarkerTerminatedString => self.deserialize_str(visitor),
-            Tag::Bytes => self.deserialize_bytes(visitor),
+            Tag::String | Tag::MarkerTerminatedString | Tag::ChunkedString => {
+                self.deserialize_str(visitor)
+            }
+            Tag::Bytes | Tag::ChunkedBytes => self.deserialize_bytes(visitor),
             Tag::Unit => self.deserialize_unit(visitor),
             Tag::UnitStruct => self.deserialize_unit_struct("", visitor),
             Tag::NewTypeStruct => self.deserialize_newtype_struct("", visitor),
             Tag::Seq | Tag::UnsizedSeq | Tag::Tuple | Tag::TupleStruct => {
-                self.deserialize_seq(visitor)
+                self.visit_seq(None, visitor)
             }
+            // serde buffers enums, for untagged enums or flatten, as a map of their variant to its content.
             Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
-                self.deserialize_enum("", &[], visitor)
+                visitor.visit_map(VariantMapDeserializer {
+                    de: self,
+                    tag: None,
+                })
             }
-            Tag::Map | Tag::UnsizedMap | Tag::Struct => self.deserialize_map(visitor),
+            Tag::Map | Tag::UnsizedMap | Tag::Struct => self.visit_map(visitor),
             #[cfg(not(no_integer128))]
             Tag::I128 => self.deserialize_i128(visitor),
             #[cfg(not(no_integer128))]
             Tag::U128 => self.deserialize_u128(visitor),
-            got @ Tag::UnsizedSeqEnd => {
+            got @ (Tag::UnsizedSeqEnd | Tag::TypeName) => {
                 Err(Error::UnexpectedTag(UnexpectedTag { expected: &[], got }))
             }
         }
@@ -305,10 +606,8 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     implement_number!(deserialize_f32, parse_f32, visit_f32);
     implement_number!(deserialize_f64, parse_f64, visit_f64);
 
-    serde_if_integer128! {
-        implement_number!(deserialize_i128, parse_i128, visit_i128);
-        implement_number!(deserialize_u128, parse_u128, visit_u128);
-    }
+    implement_number!(deserialize_i128, parse_i128, visit_i128);
+    implement_number!(deserialize_u128, parse_u128, visit_u128);
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, R::Error>
     where
@@ -338,29 +637,40 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: de::Visitor<'de>,
     {
+        self.check_strict_tag(&[Tag::String])?;
         match_tag! {
             self.pop_tag()?,
             Tag::String => {
                 let len = self.pop_len()?;
                 let str = self.pop_str(len)?;
-                #[cfg(feature = "alloc")]
                 match str {
-                    alloc::borrow::Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
-                    alloc::borrow::Cow::Owned(string) => visitor.visit_string(string)
+                    Reference::Borrowed(str) => visitor.visit_borrowed_str(str),
+                    Reference::Copied(str) => visitor.visit_str(str)
                 }
-                #[cfg(not(feature = "alloc"))]
-                visitor.visit_borrowed_str(str)
 
             },
             Tag::MarkerTerminatedString => {
                 let str = self.pop_unsized_str()?;
-                #[cfg(feature = "alloc")]
                 match str {
-                    alloc::borrow::Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
-                    alloc::borrow::Cow::Owned(string) => visitor.visit_string(string)
+                    Reference::Borrowed(str) => visitor.visit_borrowed_str(str),
+                    Reference::Copied(str) => visitor.visit_str(str)
+                }
+            },
+            Tag::ChunkedString => {
+                let bytes = self.pop_chunks()?;
+                #[cfg(feature = "alloc")]
+                match bytes {
+                    alloc::borrow::Cow::Borrowed(bytes) => {
+                        visitor.visit_borrowed_str(core::str::from_utf8(bytes).map_err(Error::Utf8Error)?)
+                    }
+                    alloc::borrow::Cow::Owned(bytes) => {
+                        let string = alloc::string::String::from_utf8(bytes)
+                            .map_err(|err| Error::Utf8Error(err.utf8_error()))?;
+                        visitor.visit_string(string)
+                    }
                 }
                 #[cfg(not(feature = "alloc"))]
-                visitor.visit_borrowed_str(str)
+                visitor.visit_borrowed_str(core::str::from_utf8(bytes).map_err(Error::Utf8Error)?)
             }
         }
     }
@@ -376,11 +686,19 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: de::Visitor<'de>,
     {
+        self.check_strict_tag(&[Tag::Bytes])?;
         match_tag! {
             self.pop_tag()?,
             Tag::Bytes => {
                 let len = self.pop_len()?;
                 let bytes = self.reader.read_bytes(len)?;
+                match bytes {
+                    Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                    Reference::Copied(bytes) => visitor.visit_bytes(bytes)
+                }
+            },
+            Tag::ChunkedBytes => {
+                let bytes = self.pop_chunks()?;
                 #[cfg(feature = "alloc")]
                 match bytes {
                     alloc::borrow::Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
@@ -422,12 +740,13 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
 
     fn deserialize_unit_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
+        self.check_type_name(name)?;
         match_tag! {
             self.pop_tag()?,
             Tag::UnitStruct => visitor.visit_unit()
@@ -436,12 +755,24 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
 
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
+        if name == STREAMED_BYTES {
+            self.check_strict_tag(&[Tag::Bytes])?;
+            return match_tag! {
+                self.pop_tag()?,
+                Tag::Bytes => {
+                    let len = self.pop_len()?;
+                    visitor.visit_seq(StreamedBytesDeserializer::new(self, Some(len)))
+                },
+                Tag::ChunkedBytes => visitor.visit_seq(StreamedBytesDeserializer::new(self, None))
+            };
+        }
+        self.check_type_name(name)?;
         match_tag! {
             self.pop_tag()?,
             Tag::NewTypeStruct => visitor.visit_newtype_struct(self)
@@ -452,76 +783,64 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: de::Visitor<'de>,
     {
-        let len = match_tag! {
-            self.pop_tag()?,
-            Tag::Seq | Tag::Tuple | Tag::TupleStruct => {
-                self.pop_len().map(Some)
-            },
-            Tag::UnsizedSeq => {
-                Ok(None)
-            }
-        }?;
-
-        visitor.visit_seq(SeqDeserializer::new(self, len))
+        self.check_strict_tag(&[Tag::Seq])?;
+        self.visit_seq(None, visitor)
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, R::Error>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.check_strict_tag(&[Tag::Tuple])?;
+        self.visit_seq(Some(len), visitor)
     }
 
     fn deserialize_tuple_struct<V>(
         self,
-        _name: &'static str,
-        _len: usize,
+        name: &'static str,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.check_type_name(name)?;
+        self.check_strict_tag(&[Tag::TupleStruct])?;
+        self.visit_seq(Some(len), visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
-        let len = match_tag! {
-            self.pop_tag()?,
-            Tag::Map | Tag::Struct => {
-                self.pop_len().map(Some)
-            },
-            Tag::UnsizedMap => {
-                Ok(None)
-            }
-        }?;
-
-        visitor.visit_map(SeqDeserializer::new(self, len))
+        self.check_strict_tag(&[Tag::Map])?;
+        self.visit_map(visitor)
     }
 
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        self.check_type_name(name)?;
+        self.check_strict_tag(&[Tag::Struct])?;
+        self.visit_map(visitor)
     }
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, R::Error>
     where
         V: de::Visitor<'de>,
     {
+        self.check_type_name(name)?;
         visitor.visit_enum(self)
     }
 
@@ -529,12 +848,18 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: de::Visitor<'de>,
     {
+        self.check_strict_tag(&[
+            Tag::UnitVariant,
+            Tag::NewTypeVariant,
+            Tag::TupleVariant,
+            Tag::StructVariant,
+            Tag::String,
+        ])?;
         let tag = self.pop_tag()?;
         match_tag! {
             tag,
             Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
-                let variant_index = self.pop_variant()?;
-                let value = visitor.visit_u32::<Self::Error>(variant_index)?;
+                let value = self.visit_variant_identifier(visitor)?;
                 // carry tag to check de::VariantAccess impl
                 self.peeked_tag = Some(tag);
                 Ok(value)
@@ -542,13 +867,10 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
             Tag::String => {
                 let len = self.pop_len()?;
                 let str = self.pop_str(len)?;
-                #[cfg(feature = "alloc")]
                 match str {
-                    alloc::borrow::Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
-                    alloc::borrow::Cow::Owned(string) => visitor.visit_string(string)
+                    Reference::Borrowed(str) => visitor.visit_borrowed_str(str),
+                    Reference::Copied(str) => visitor.visit_str(str)
                 }
-                #[cfg(not(feature = "alloc"))]
-                visitor.visit_borrowed_str(str)
             }
         }
     }
@@ -561,32 +883,165 @@ impl<'a, 'de, R: Read<'de>> de::Deserializer<'de> for &'a mut Deserializer<R> {
     }
 }
 
-struct SeqDeserializer<'a, R> {
+/// Present a byte array as a tuple of its length followed by chunks of at most `STREAM_CHUNK_SIZE` bytes,
+/// so it can be consumed without being read at once.
+///
+/// The length of a chunked byte array isn't known before its end, it is given as a unit instead.
+struct StreamedBytesDeserializer<'a, R> {
     de: &'a mut Deserializer<R>,
-    remaining: Option<usize>,
+    len: Option<Option<usize>>,
+    // bytes left in the byte array, or in the current chunk of a chunked one
+    remaining: usize,
+    // whether chunks are left to read up to the end marker
+    chunked: bool,
 }
 
-impl<'a, 'de: 'a, R: Read<'de>> SeqDeserializer<'a, R> {
+impl<'a, 'de: 'a, R: Read<'de>> StreamedBytesDeserializer<'a, R> {
     fn new(de: &'a mut Deserializer<R>, len: Option<usize>) -> Self {
-        SeqDeserializer { de, remaining: len }
+        StreamedBytesDeserializer {
+            de,
+            len: Some(len),
+            remaining: len.unwrap_or(0),
+            chunked: len.is_none(),
+        }
     }
+}
 
-    fn parse_next<T>(&mut self, seed: T) -> Result<Option<T::Value>, R::Error>
+impl<'a, 'de: 'a, R: Read<'de>> de::SeqAccess<'de> for StreamedBytesDeserializer<'a, R> {
+    type Error = Error<R::Error>;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, R::Error>
     where
         T: de::DeserializeSeed<'de>,
     {
+        use de::value::{BorrowedBytesDeserializer, BytesDeserializer};
+        use de::IntoDeserializer;
+        match self.len.take() {
+            Some(Some(len)) => return seed.deserialize((len as u64).into_deserializer()).map(Some),
+            Some(None) => {
+                return seed
+                    .deserialize(de::value::UnitDeserializer::new())
+                    .map(Some)
+            }
+            None => {}
+        }
+        while self.chunked && self.remaining == 0 {
+            if self.de.peek_tag()? == Tag::UnsizedSeqEnd {
+                self.de.pop_tag()?;
+                self.chunked = false;
+            } else {
+                self.remaining = self.de.pop_len()?;
+            }
+        }
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let len = self.remaining.min(STREAM_CHUNK_SIZE);
+        self.remaining -= len;
+        match self.de.reader.read_bytes(len)? {
+            Reference::Borrowed(chunk) => seed.deserialize(BorrowedBytesDeserializer::new(chunk)),
+            Reference::Copied(chunk) => seed.deserialize(BytesDeserializer::new(chunk)),
+        }
+        .map(Some)
+    }
+}
+
+struct SeqDeserializer<'a, R> {
+    de: &'a mut Deserializer<R>,
+    remaining: Option<usize>,
+    // whether keys must be sorted by their encoding, in strict mode
+    #[cfg(feature = "alloc")]
+    sorted_keys: bool,
+    // encoding of the last key read, when keys must be sorted
+    #[cfg(feature = "alloc")]
+    last_key: Option<Vec<u8>>,
+}
+
+impl<'a, 'de: 'a, R: Read<'de>> SeqDeserializer<'a, R> {
+    fn new(de: &'a mut Deserializer<R>, len: Option<usize>) -> Self {
+        SeqDeserializer {
+            de,
+            remaining: len,
+            #[cfg(feature = "alloc")]
+            sorted_keys: false,
+            #[cfg(feature = "alloc")]
+            last_key: None,
+        }
+    }
+
+    /// Deserializer of map entries, whose keys must be sorted by their encoding if `sorted_keys` is set.
+    fn map(de: &'a mut Deserializer<R>, len: Option<usize>, sorted_keys: bool) -> Self {
+        #[cfg(not(feature = "alloc"))]
+        let _ = sorted_keys;
+        SeqDeserializer {
+            #[cfg(feature = "alloc")]
+            sorted_keys,
+            ..SeqDeserializer::new(de, len)
+        }
+    }
+
+    fn has_next(&mut self) -> Result<bool, R::Error> {
         if let Some(remaining) = self.remaining.as_mut() {
             if *remaining == 0 {
-                return Ok(None);
+                return Ok(false);
             }
             *remaining -= 1;
         } else if self.de.peek_tag()? == Tag::UnsizedSeqEnd {
             self.de.pop_tag()?;
-            return Ok(None);
+            return Ok(false);
         }
+        Ok(true)
+    }
 
+    fn parse_next<T>(&mut self, seed: T) -> Result<Option<T::Value>, R::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if !self.has_next()? {
+            return Ok(None);
+        }
         seed.deserialize(&mut *self.de).map(Some)
     }
+
+    /// Read a key, checking it comes after the previous one when keys must be sorted.
+    #[cfg(feature = "alloc")]
+    fn parse_key<K>(&mut self, seed: K) -> Result<Option<K::Value>, R::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if !self.sorted_keys {
+            return self.parse_next(seed);
+        }
+        if !self.has_next()? {
+            return Ok(None);
+        }
+        // only a `Recorder` gives back the bytes of keys
+        let Some(start) = self.de.reader.start_recording() else {
+            return seed.deserialize(&mut *self.de).map(Some);
+        };
+        let peeked_tag = self.de.peeked_tag;
+        let key = seed.deserialize(&mut *self.de);
+        let mut bytes = self.de.reader.stop_recording(start);
+        let key = key?;
+        if let Some(tag) = peeked_tag {
+            bytes.insert(0, tag as u8);
+        }
+        match self.last_key.as_ref().map(|last_key| last_key.cmp(&bytes)) {
+            Some(Ordering::Equal) => return Err(Error::DuplicateKey),
+            Some(Ordering::Greater) => return Err(Error::UnsortedKeys),
+            _ => {}
+        }
+        self.last_key = Some(bytes);
+        Ok(Some(key))
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn parse_key<K>(&mut self, seed: K) -> Result<Option<K::Value>, R::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        self.parse_next(seed)
+    }
 }
 
 impl<'a, 'de: 'a, R: Read<'de>> de::SeqAccess<'de> for SeqDeserializer<'a, R> {
@@ -611,7 +1066,7 @@ impl<'a, 'de: 'a, R: Read<'de>> de::MapAccess<'de> for SeqDeserializer<'a, R> {
     where
         K: de::DeserializeSeed<'de>,
     {
-        self.parse_next(seed)
+        self.parse_key(seed)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, R::Error>
@@ -626,6 +1081,73 @@ impl<'a, 'de: 'a, R: Read<'de>> de::MapAccess<'de> for SeqDeserializer<'a, R> {
     }
 }
 
+/// Present a variant as a map with a single entry, its identifier to its content.
+struct VariantMapDeserializer<'a, R> {
+    de: &'a mut Deserializer<R>,
+    // the tag of the variant, once its identifier is read
+    tag: Option<Tag>,
+}
+
+impl<'a, 'de: 'a, R: Read<'de>> de::MapAccess<'de> for VariantMapDeserializer<'a, R> {
+    type Error = Error<R::Error>;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, R::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.tag.is_some() {
+            return Ok(None);
+        }
+        self.tag = Some(self.de.pop_tag()?);
+        seed.deserialize(VariantIdentifierDeserializer { de: &mut *self.de })
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, R::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match_tag! {
+            self.tag.unwrap_or(Tag::UnsizedSeqEnd),
+            Tag::UnitVariant => seed.deserialize(de::value::UnitDeserializer::new()),
+            Tag::NewTypeVariant => seed.deserialize(&mut *self.de),
+            Tag::TupleVariant => {
+                self.de.peeked_tag = Some(Tag::Tuple);
+                seed.deserialize(&mut *self.de)
+            },
+            Tag::StructVariant => {
+                self.de.peeked_tag = Some(Tag::Struct);
+                seed.deserialize(&mut *self.de)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+struct VariantIdentifierDeserializer<'a, R> {
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'a, 'de: 'a, R: Read<'de>> de::Deserializer<'de> for VariantIdentifierDeserializer<'a, R> {
+    type Error = Error<R::Error>;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, R::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.visit_variant_identifier(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
 impl<'a, 'de: 'a, R: Read<'de>> de::EnumAccess<'de> for &'a mut Deserializer<R> {
     type Error = Error<R::Error>;
     type Variant = Self;
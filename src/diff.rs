@@ -0,0 +1,319 @@
+//! Structural comparison of two encoded values, without their Rust type.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    de::Result,
+    error::EndOfBuff,
+    value::{Value, VariantContent},
+};
+
+/// Step from a value to one of its parts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PathSegment {
+    /// Field of a struct or a struct variant.
+    Field(String),
+    /// Element of a sequence, a tuple or a tuple variant.
+    Index(usize),
+    /// Value of a map entry.
+    Key(Value),
+    /// Content of an option.
+    Some,
+    /// Content of a newtype struct or a newtype variant.
+    NewType,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Path(pub Vec<PathSegment>);
+
+impl Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("<root>");
+        }
+        for segment in &self.0 {
+            match segment {
+                PathSegment::Field(name) => f.write_fmt(format_args!(".{}", name))?,
+                PathSegment::Index(index) => f.write_fmt(format_args!("[{}]", index))?,
+                PathSegment::Key(key) => f.write_fmt(format_args!("[{:?}]", key))?,
+                PathSegment::Some => f.write_str("?")?,
+                PathSegment::NewType => f.write_str(".0")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    /// A struct field, map entry or sequence element is only in the new value.
+    Added(Value),
+    /// A struct field, map entry or sequence element is only in the old value.
+    Removed(Value),
+    Changed {
+        from: Value,
+        to: Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: Path,
+    pub kind: ChangeKind,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ChangeKind::Added(value) => {
+                f.write_fmt(format_args!("{}: added {:?}", self.path, value))
+            }
+            ChangeKind::Removed(value) => {
+                f.write_fmt(format_args!("{}: removed {:?}", self.path, value))
+            }
+            ChangeKind::Changed { from, to } => f.write_fmt(format_args!(
+                "{}: changed from {:?} to {:?}",
+                self.path, from, to
+            )),
+        }
+    }
+}
+
+/// Every change from the encoded value `a` to the encoded value `b`.
+///
+/// Differences that only come from the encoding, such as number widths or sized and unsized sequences,
+/// are not changes. Sequence elements are compared by index, trailing removed elements are reported from the last one.
+/// Floats are compared by their bits, so a NaN is unchanged.
+///
+/// Both values are fully decoded as `Value`s before being compared, so both are held in memory at once,
+/// taking several times the size of their encoding.
+pub fn diff(a: &[u8], b: &[u8]) -> Result<Vec<Change>, EndOfBuff> {
+    let a = Value::from_bytes(a)?;
+    let b = Value::from_bytes(b)?;
+    Ok(diff_values(&a, &b))
+}
+
+/// Every change from `a` to `b`, see `diff`.
+pub fn diff_values(a: &Value, b: &Value) -> Vec<Change> {
+    let mut differ = Differ::default();
+    differ.compare(a, b);
+    differ.changes
+}
+
+#[derive(Default)]
+struct Differ {
+    path: Vec<PathSegment>,
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn report(&mut self, kind: ChangeKind) {
+        self.changes.push(Change {
+            path: Path(self.path.clone()),
+            kind,
+        });
+    }
+
+    fn with_segment<F: FnOnce(&mut Self)>(&mut self, segment: PathSegment, f: F) {
+        self.path.push(segment);
+        f(self);
+        self.path.pop();
+    }
+
+    fn compare(&mut self, a: &Value, b: &Value) {
+        match (a, b) {
+            (Value::Option(Some(a)), Value::Option(Some(b))) => {
+                self.with_segment(PathSegment::Some, |this| this.compare(a, b))
+            }
+            (Value::NewTypeStruct(a), Value::NewTypeStruct(b)) => {
+                self.with_segment(PathSegment::NewType, |this| this.compare(a, b))
+            }
+            (Value::Seq(a), Value::Seq(b)) => self.compare_seqs(a, b),
+            (Value::Tuple(a), Value::Tuple(b)) | (Value::TupleStruct(a), Value::TupleStruct(b))
+                if a.len() == b.len() =>
+            {
+                self.compare_elements(a, b)
+            }
+            (Value::Map(a), Value::Map(b)) => self.compare_entries(a, b),
+            (Value::Struct(a), Value::Struct(b)) => self.compare_fields(a, b),
+            (
+                Value::Variant { id, content },
+                Value::Variant {
+                    id: other_id,
+                    content: other_content,
+                },
+            ) if id == other_id => match (content, other_content) {
+                (VariantContent::Unit, VariantContent::Unit) => {}
+                (VariantContent::NewType(a), VariantContent::NewType(b)) => {
+                    self.with_segment(PathSegment::NewType, |this| this.compare(a, b))
+                }
+                (VariantContent::Tuple(a), VariantContent::Tuple(b)) if a.len() == b.len() => {
+                    self.compare_elements(a, b)
+                }
+                (VariantContent::Struct(a), VariantContent::Struct(b)) => self.compare_fields(a, b),
+                _ => self.changed(a, b),
+            },
+            (a, b) if a.same(b) => {}
+            (a, b) => self.changed(a, b),
+        }
+    }
+
+    fn changed(&mut self, a: &Value, b: &Value) {
+        self.report(ChangeKind::Changed {
+            from: a.clone(),
+            to: b.clone(),
+        })
+    }
+
+    fn compare_elements(&mut self, a: &[Value], b: &[Value]) {
+        for (index, (a, b)) in a.iter().zip(b).enumerate() {
+            self.with_segment(PathSegment::Index(index), |this| this.compare(a, b));
+        }
+    }
+
+    fn compare_seqs(&mut self, a: &[Value], b: &[Value]) {
+        self.compare_elements(a, b);
+        // removed from the end so the indexes stay valid when the changes are applied in order.
+        for index in (b.len()..a.len()).rev() {
+            self.with_segment(PathSegment::Index(index), |this| {
+                this.report(ChangeKind::Removed(a[index].clone()))
+            });
+        }
+        for (index, value) in b.iter().enumerate().skip(a.len()) {
+            self.with_segment(PathSegment::Index(index), |this| {
+                this.report(ChangeKind::Added(value.clone()))
+            });
+        }
+    }
+
+    fn compare_entries(&mut self, a: &[(Value, Value)], b: &[(Value, Value)]) {
+        for (key, value) in a {
+            let segment = PathSegment::Key(key.clone());
+            match b.iter().find(|(other_key, _)| other_key.same(key)) {
+                Some((_, other)) => self.with_segment(segment, |this| this.compare(value, other)),
+                None => self.with_segment(segment, |this| {
+                    this.report(ChangeKind::Removed(value.clone()))
+                }),
+            }
+        }
+        for (key, value) in b {
+            if !a.iter().any(|(other_key, _)| other_key.same(key)) {
+                self.with_segment(PathSegment::Key(key.clone()), |this| {
+                    this.report(ChangeKind::Added(value.clone()))
+                });
+            }
+        }
+    }
+
+    fn compare_fields(&mut self, a: &[(String, Value)], b: &[(String, Value)]) {
+        for (name, value) in a {
+            let segment = PathSegment::Field(name.clone());
+            match b.iter().find(|(other_name, _)| other_name == name) {
+                Some((_, other)) => self.with_segment(segment, |this| this.compare(value, other)),
+                None => self.with_segment(segment, |this| {
+                    this.report(ChangeKind::Removed(value.clone()))
+                }),
+            }
+        }
+        for (name, value) in b {
+            if !a.iter().any(|(other_name, _)| other_name == name) {
+                self.with_segment(PathSegment::Field(name.clone()), |this| {
+                    this.report(ChangeKind::Added(value.clone()))
+                });
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use serde::ser::{SerializeStruct, Serializer};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Config {
+        name: String,
+        ports: Vec<u64>,
+        limits: BTreeMap<String, u32>,
+        backup: Option<String>,
+    }
+
+    /// Same content as `Config` written with an unsized sequence and a marker terminated string.
+    struct UnsizedConfig<'a>(&'a Config);
+
+    impl Serialize for UnsizedConfig<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+            struct Ports<'a>(&'a [u64]);
+            impl Serialize for Ports<'_> {
+                fn serialize<S: Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> core::result::Result<S::Ok, S::Error> {
+                    // a filter has no exact size hint
+                    serializer.collect_seq(self.0.iter().filter(|_| true))
+                }
+            }
+            struct Name<'a>(&'a str);
+            impl Serialize for Name<'_> {
+                fn serialize<S: Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> core::result::Result<S::Ok, S::Error> {
+                    serializer.collect_str(self.0)
+                }
+            }
+            let mut s = serializer.serialize_struct("Config", 4)?;
+            s.serialize_field("name", &Name(&self.0.name))?;
+            s.serialize_field("ports", &Ports(&self.0.ports))?;
+            s.serialize_field("limits", &self.0.limits)?;
+            s.serialize_field("backup", &self.0.backup)?;
+            s.end()
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Config {
+            name: "server".into(),
+            ports: vec![80, 443, 8080],
+            limits: BTreeMap::from([("conn".into(), 100), ("rate".into(), 10)]),
+            backup: Some("a".into()),
+        };
+        let old_bytes = crate::to_bytes(&old).unwrap();
+        let unsized_bytes = crate::to_bytes(&UnsizedConfig(&old)).unwrap();
+        assert_ne!(old_bytes, unsized_bytes);
+        assert_eq!(diff(&old_bytes, &unsized_bytes).unwrap(), vec![]);
+
+        let new = Config {
+            name: "server".into(),
+            ports: vec![80, 70000],
+            limits: BTreeMap::from([("conn".into(), 200), ("mem".into(), 1)]),
+            backup: Some("b".into()),
+        };
+        let new_bytes = crate::to_bytes(&new).unwrap();
+        let changes: Vec<String> = diff(&old_bytes, &new_bytes)
+            .unwrap()
+            .iter()
+            .map(|change| change.to_string())
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ".ports[1]: changed from Unsigned(443) to Unsigned(70000)",
+                ".ports[2]: removed Unsigned(8080)",
+                ".limits[String(\"conn\")]: changed from Unsigned(100) to Unsigned(200)",
+                ".limits[String(\"rate\")]: removed Unsigned(10)",
+                ".limits[String(\"mem\")]: added Unsigned(1)",
+                ".backup?: changed from String(\"a\") to String(\"b\")",
+            ]
+        );
+
+        let nan = crate::to_bytes(&(1u8, f64::NAN, BTreeMap::from([(1u8, f32::NAN)]))).unwrap();
+        assert_eq!(diff(&nan, &nan).unwrap(), vec![]);
+        let other = crate::to_bytes(&(1u8, 0.5f64, BTreeMap::from([(1u8, f32::NAN)]))).unwrap();
+        assert_eq!(diff(&nan, &other).unwrap().len(), 1);
+    }
+}
@@ -1,4 +1,4 @@
-use core::fmt::{self, Debug, Display};
+use core::fmt::{self, Debug, Display, Write};
 use core::str::Utf8Error;
 use serde::{de, ser};
 
@@ -28,23 +28,109 @@ impl Display for NoRWError {
 #[cfg(feature = "std")]
 impl std::error::Error for NoRWError {}
 
+/// Bytes a `Message` holds, longer messages are truncated.
+pub const MESSAGE_CAPACITY: usize = 48;
+
+/// Error message stored inline, so errors keep their message without an allocator.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    bytes: [u8; MESSAGE_CAPACITY],
+    len: u8,
+    truncated: bool,
+}
+
+impl Message {
+    pub fn new(msg: impl Display) -> Self {
+        let mut message = Message {
+            bytes: [0; MESSAGE_CAPACITY],
+            len: 0,
+            truncated: false,
+        };
+        // writing to a message never fails, it truncates.
+        let _ = write!(message, "{}", msg);
+        message
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole chars are written.
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    /// Whether the message didn't fit and was cut.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+        let start = self.len as usize;
+        let mut len = s.len().min(MESSAGE_CAPACITY - start);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.bytes[start..start + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len as u8;
+        self.truncated = len < s.len();
+        Ok(())
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Text held by errors, inline in a `Message` without an allocator.
+#[cfg(feature = "alloc")]
+pub type ErrorText = String;
+#[cfg(not(feature = "alloc"))]
+pub type ErrorText = Message;
+
+pub(crate) fn error_text(msg: impl Display) -> ErrorText {
+    #[cfg(feature = "alloc")]
+    return msg.to_string();
+    #[cfg(not(feature = "alloc"))]
+    return Message::new(msg);
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SerError<We> {
     WriteError(We),
-    #[cfg(feature = "alloc")]
-    Custom(String),
-    #[cfg(not(feature = "alloc"))]
-    Custom,
+    /// A sequence, map or struct got another number of elements than the len it was started with.
+    LenMismatch {
+        declared: usize,
+        written: usize,
+    },
+    /// In canonical mode, a map has the same key twice.
+    DuplicateKey,
+    Custom(ErrorText),
 }
 
 impl<We: RWError> Display for SerError<We> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SerError::WriteError(err) => Display::fmt(err, f),
-            #[cfg(feature = "alloc")]
+            SerError::LenMismatch { declared, written } => f.write_fmt(format_args!(
+                "Declared a len of {} but serialized {} elements.",
+                declared, written
+            )),
+            SerError::DuplicateKey => f.write_str("A map has the same key twice."),
             SerError::Custom(err) => Display::fmt(err, f),
-            #[cfg(not(feature = "alloc"))]
-            SerError::Custom => f.write_str("An error occured."),
         }
     }
 }
@@ -60,20 +146,11 @@ impl<We: RWError> std::error::Error for SerError<We> {
 }
 
 impl<We: RWError> ser::Error for SerError<We> {
-    #[cfg(feature = "alloc")]
     fn custom<T>(msg: T) -> Self
     where
         T: Display,
     {
-        SerError::Custom(msg.to_string())
-    }
-
-    #[cfg(not(feature = "alloc"))]
-    fn custom<T>(_msg: T) -> Self
-    where
-        T: Display,
-    {
-        SerError::Custom
+        SerError::Custom(error_text(msg))
     }
 }
 
@@ -90,10 +167,148 @@ pub enum DeError<E> {
     Utf8Error(Utf8Error),
     InvalidLen(u64),
     UnexpectedTag(UnexpectedTag),
-    #[cfg(feature = "alloc")]
-    Custom(String),
     #[cfg(not(feature = "alloc"))]
-    Custom,
+    ChunksWithoutAlloc,
+    /// The type name written with a value is not the one of the type it is decoded as.
+    #[cfg(feature = "alloc")]
+    UnexpectedTypeName {
+        expected: &'static str,
+        got: String,
+    },
+    /// A type name refers by its index to a name not read before.
+    #[cfg(feature = "alloc")]
+    UnknownTypeName(usize),
+    InvalidType {
+        unexpected: UnexpectedText,
+        expected: ErrorText,
+    },
+    InvalidLength {
+        len: usize,
+        expected: ErrorText,
+    },
+    UnknownField {
+        field: ErrorText,
+        expected: &'static [&'static str],
+    },
+    MissingField(&'static str),
+    UnknownVariant {
+        variant: ErrorText,
+        expected: &'static [&'static str],
+    },
+    /// In strict mode, a number or len written with a bigger tag than needed.
+    NonMinimal(Tag),
+    /// In strict mode, a key written twice in the same map.
+    DuplicateKey,
+    /// In strict mode, map keys not sorted by their encoding.
+    UnsortedKeys,
+    /// In strict mode, bytes left after the value.
+    TrailingBytes,
+    Custom(ErrorText),
+}
+
+// errors are returned by value everywhere, they must stay small without an allocator too.
+const _: () = assert!(core::mem::size_of::<DeError<NoRWError>>() <= 96);
+const _: () = assert!(core::mem::size_of::<SerError<NoRWError>>() <= 96);
+
+/// Value found instead of the expected one, as serde describes it, reduced to an `Unexpected` without an allocator.
+#[cfg(feature = "alloc")]
+pub type UnexpectedText = String;
+#[cfg(not(feature = "alloc"))]
+pub type UnexpectedText = Unexpected;
+
+/// Kind of the value found instead of the expected one, like `serde::de::Unexpected` but owned and small:
+/// floats, strings and bytes don't keep their value.
+#[cfg(not(feature = "alloc"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unexpected {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float,
+    Char(char),
+    Str,
+    Bytes,
+    Unit,
+    Option,
+    NewtypeStruct,
+    Seq,
+    Map,
+    Enum,
+    UnitVariant,
+    NewtypeVariant,
+    TupleVariant,
+    StructVariant,
+    Other,
+}
+
+#[cfg(not(feature = "alloc"))]
+impl From<de::Unexpected<'_>> for Unexpected {
+    fn from(value: de::Unexpected<'_>) -> Self {
+        match value {
+            de::Unexpected::Bool(v) => Unexpected::Bool(v),
+            de::Unexpected::Unsigned(v) => Unexpected::Unsigned(v),
+            de::Unexpected::Signed(v) => Unexpected::Signed(v),
+            de::Unexpected::Float(_) => Unexpected::Float,
+            de::Unexpected::Char(v) => Unexpected::Char(v),
+            de::Unexpected::Str(_) => Unexpected::Str,
+            de::Unexpected::Bytes(_) => Unexpected::Bytes,
+            de::Unexpected::Unit => Unexpected::Unit,
+            de::Unexpected::Option => Unexpected::Option,
+            de::Unexpected::NewtypeStruct => Unexpected::NewtypeStruct,
+            de::Unexpected::Seq => Unexpected::Seq,
+            de::Unexpected::Map => Unexpected::Map,
+            de::Unexpected::Enum => Unexpected::Enum,
+            de::Unexpected::UnitVariant => Unexpected::UnitVariant,
+            de::Unexpected::NewtypeVariant => Unexpected::NewtypeVariant,
+            de::Unexpected::TupleVariant => Unexpected::TupleVariant,
+            de::Unexpected::StructVariant => Unexpected::StructVariant,
+            de::Unexpected::Other(_) => Unexpected::Other,
+        }
+    }
+}
+
+#[cfg(not(feature = "alloc"))]
+impl Display for Unexpected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unexpected::Bool(v) => f.write_fmt(format_args!("boolean `{}`", v)),
+            Unexpected::Unsigned(v) => f.write_fmt(format_args!("integer `{}`", v)),
+            Unexpected::Signed(v) => f.write_fmt(format_args!("integer `{}`", v)),
+            Unexpected::Float => f.write_str("floating point"),
+            Unexpected::Char(v) => f.write_fmt(format_args!("character `{}`", v)),
+            Unexpected::Str => f.write_str("string"),
+            Unexpected::Bytes => f.write_str("byte array"),
+            Unexpected::Unit => f.write_str("unit value"),
+            Unexpected::Option => f.write_str("Option value"),
+            Unexpected::NewtypeStruct => f.write_str("newtype struct"),
+            Unexpected::Seq => f.write_str("sequence"),
+            Unexpected::Map => f.write_str("map"),
+            Unexpected::Enum => f.write_str("enum"),
+            Unexpected::UnitVariant => f.write_str("unit variant"),
+            Unexpected::NewtypeVariant => f.write_str("newtype variant"),
+            Unexpected::TupleVariant => f.write_str("tuple variant"),
+            Unexpected::StructVariant => f.write_str("struct variant"),
+            Unexpected::Other => f.write_str("other value"),
+        }
+    }
+}
+
+/// Write the names of `expected` as "one of `a`, `b`".
+fn fmt_one_of(expected: &[&str], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match expected {
+        [] => f.write_str("none"),
+        [name] => f.write_fmt(format_args!("`{}`", name)),
+        names => {
+            f.write_str("one of ")?;
+            for (i, name) in names.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                f.write_fmt(format_args!("`{}`", name))?;
+            }
+            Ok(())
+        }
+    }
 }
 
 impl<E: RWError> Display for DeError<E> {
@@ -102,14 +317,53 @@ impl<E: RWError> Display for DeError<E> {
             DeError::ReaderError(err) => Display::fmt(err, f),
             DeError::TagParsingError(err) => Display::fmt(err, f),
             DeError::Utf8Error(err) => Display::fmt(err, f),
-            #[cfg(feature = "alloc")]
             DeError::Custom(err) => Display::fmt(err, f),
-            #[cfg(not(feature = "alloc"))]
-            DeError::Custom => f.write_str("An error occured."),
+            DeError::InvalidType {
+                unexpected,
+                expected,
+            } => f.write_fmt(format_args!(
+                "invalid type: {}, expected {}",
+                unexpected, expected
+            )),
+            DeError::InvalidLength { len, expected } => f.write_fmt(format_args!(
+                "invalid length {}, expected {}",
+                len, expected
+            )),
+            DeError::UnknownField { field, expected } => {
+                f.write_fmt(format_args!("unknown field `{}`, expected ", field))?;
+                fmt_one_of(expected, f)
+            }
+            DeError::MissingField(field) => {
+                f.write_fmt(format_args!("missing field `{}`", field))
+            }
+            DeError::UnknownVariant { variant, expected } => {
+                f.write_fmt(format_args!("unknown variant `{}`, expected ", variant))?;
+                fmt_one_of(expected, f)
+            }
             DeError::UnexpectedTag(err) => Display::fmt(err, f),
+            DeError::NonMinimal(tag) => {
+                f.write_fmt(format_args!("Value doesn't need the tag {:?}", tag))
+            }
+            DeError::DuplicateKey => f.write_str("A map has the same key twice."),
+            DeError::UnsortedKeys => f.write_str("Map keys are not sorted."),
+            DeError::TrailingBytes => f.write_str("Bytes are left after the value."),
             DeError::InvalidLen(len) => {
                 f.write_fmt(format_args!("Sequence len is too big: {}", len))
             }
+            #[cfg(feature = "alloc")]
+            DeError::UnexpectedTypeName { expected, got } => f.write_fmt(format_args!(
+                "Expected a value of type {} but got one of type {}",
+                expected, got
+            )),
+            #[cfg(feature = "alloc")]
+            DeError::UnknownTypeName(index) => f.write_fmt(format_args!(
+                "No type name was read before with the index {}",
+                index
+            )),
+            #[cfg(not(feature = "alloc"))]
+            DeError::ChunksWithoutAlloc => f.write_str(
+                "Reading chunked data that is split or not borrowed requires the \"alloc\" feature.",
+            ),
         }
     }
 }
@@ -118,20 +372,46 @@ impl<E: RWError> Display for DeError<E> {
 impl<E: RWError> std::error::Error for DeError<E> {}
 
 impl<E: RWError> de::Error for DeError<E> {
-    #[cfg(feature = "alloc")]
     fn custom<T>(msg: T) -> Self
     where
         T: Display,
     {
-        DeError::Custom(msg.to_string())
+        DeError::Custom(error_text(msg))
     }
 
-    #[cfg(not(feature = "alloc"))]
-    fn custom<T>(_msg: T) -> Self
-    where
-        T: Display,
-    {
-        DeError::Custom
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        DeError::InvalidType {
+            #[cfg(feature = "alloc")]
+            unexpected: error_text(unexp),
+            #[cfg(not(feature = "alloc"))]
+            unexpected: unexp.into(),
+            expected: error_text(format_args!("{}", exp)),
+        }
+    }
+
+    fn invalid_length(len: usize, exp: &dyn de::Expected) -> Self {
+        DeError::InvalidLength {
+            len,
+            expected: error_text(format_args!("{}", exp)),
+        }
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        DeError::UnknownField {
+            field: error_text(field),
+            expected,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        DeError::MissingField(field)
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        DeError::UnknownVariant {
+            variant: error_text(variant),
+            expected,
+        }
     }
 }
 
@@ -167,3 +447,113 @@ impl Display for EndOfBuff {
 
 #[cfg(feature = "std")]
 impl std::error::Error for EndOfBuff {}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_structured_errors() {
+        let message = Message::new(format_args!("{}é", "a".repeat(MESSAGE_CAPACITY - 1)));
+        assert!(message.is_truncated());
+        assert_eq!(message.as_str().len(), MESSAGE_CAPACITY - 1);
+        assert_eq!(Message::new("short").to_string(), "short");
+
+        #[derive(Debug, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        #[allow(dead_code)]
+        struct Config {
+            retries: u8,
+        }
+
+        let bytes = crate::to_bytes(&[1u8]).unwrap();
+        let err = crate::from_bytes::<(u8, u8)>(&bytes).unwrap_err();
+        assert!(matches!(err, DeError::InvalidLength { len: 1, .. }));
+
+        let map = alloc::collections::BTreeMap::from([("timeout", 5u8)]);
+        let bytes = crate::to_bytes(&map).unwrap();
+        let err = crate::from_bytes::<Config>(&bytes).unwrap_err();
+        match err {
+            DeError::UnknownField { field, expected } => {
+                assert_eq!(field.as_str(), "timeout");
+                assert_eq!(expected, ["retries"]);
+            }
+            err => panic!("{:?}", err),
+        }
+
+        let bytes = crate::to_bytes(&alloc::collections::BTreeMap::<&str, u8>::new()).unwrap();
+        let err = crate::from_bytes::<Config>(&bytes).unwrap_err();
+        assert_eq!(err, DeError::MissingField("retries"));
+        assert_eq!(err.to_string(), "missing field `retries`");
+    }
+}
+
+/// Runs without any feature, so the inline messages used without `alloc` are covered too.
+#[cfg(test)]
+mod message_tests {
+    use super::*;
+    use serde::de::{Deserialize, Deserializer, Error};
+
+    #[derive(Debug)]
+    struct Long;
+
+    impl<'de> Deserialize<'de> for Long {
+        fn deserialize<D: Deserializer<'de>>(_deserializer: D) -> Result<Self, D::Error> {
+            Err(D::Error::custom(format_args!("{:>80}", "too long")))
+        }
+    }
+
+    #[test]
+    fn test_inline_messages() {
+        let err = crate::from_bytes::<Long>(&[]).unwrap_err();
+        let DeError::Custom(text) = &err else {
+            panic!("{:?}", err);
+        };
+        #[cfg(not(feature = "alloc"))]
+        {
+            assert!(text.is_truncated());
+            assert_eq!(text.as_str().len(), MESSAGE_CAPACITY);
+        }
+        #[cfg(feature = "alloc")]
+        assert_eq!(text.len(), 80);
+
+        let err = DeError::<NoRWError>::invalid_type(de::Unexpected::Unsigned(5), &"a string");
+        assert_eq!(
+            Message::new(&err).as_str(),
+            "invalid type: integer `5`, expected a string"
+        );
+
+        // the value of strings, bytes and floats is only kept with `alloc`.
+        let err = DeError::<NoRWError>::invalid_type(de::Unexpected::Str("abc"), &"a number");
+        #[cfg(feature = "alloc")]
+        {
+            assert_eq!(
+                err,
+                DeError::InvalidType {
+                    unexpected: "string \"abc\"".into(),
+                    expected: "a number".into(),
+                }
+            );
+            assert_eq!(
+                Message::new(&err).as_str(),
+                "invalid type: string \"abc\", expected a number"
+            );
+        }
+        #[cfg(not(feature = "alloc"))]
+        {
+            assert_eq!(
+                err,
+                DeError::InvalidType {
+                    unexpected: Unexpected::Str,
+                    expected: error_text("a number"),
+                }
+            );
+            assert_eq!(
+                Message::new(&err).as_str(),
+                "invalid type: string, expected a number"
+            );
+        }
+    }
+}
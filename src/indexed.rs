@@ -0,0 +1,489 @@
+//! Container of a sequence of values that can be read at any index without decoding the ones before.
+//!
+//! The values are written one after the other as regular rsbin values, followed by the table of
+//! their offsets and a footer:
+//!
+//! | values | offsets: u64 BE each | table offset: u64 BE | count: u64 BE | `INDEX_MAGIC` |
+//!
+//! Offsets are relative to the start of the container, which spans the whole slice or stream.
+
+use core::fmt::{self, Display};
+use core::marker::PhantomData;
+use core::ops::Range;
+use serde::Deserialize;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "alloc")]
+use serde::Serialize;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::{
+    de::{self, Deserializer},
+    error::{EndOfBuff, RWError},
+    read::Read,
+};
+#[cfg(feature = "std")]
+use crate::{error::DeError, read::IoReader};
+#[cfg(feature = "alloc")]
+use crate::{
+    ser::{self, Serializer},
+    write::Write,
+};
+
+/// Last bytes of an indexed container.
+pub const INDEX_MAGIC: [u8; 8] = *b"rsbinidx";
+
+const FOOTER_LEN: u64 = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error<E> {
+    /// A value could not be decoded or the reader failed.
+    Decode(de::Error<E>),
+    /// The footer or the offset table is malformed.
+    InvalidIndex,
+    OutOfBounds {
+        index: usize,
+        len: usize,
+    },
+}
+
+impl<E: RWError> Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Decode(err) => Display::fmt(err, f),
+            Error::InvalidIndex => f.write_str("Invalid index of an indexed container."),
+            Error::OutOfBounds { index, len } => f.write_fmt(format_args!(
+                "Index {} is out of bounds of an indexed container of {} values.",
+                index, len
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: RWError> std::error::Error for Error<E> {}
+
+impl<E: RWError> From<de::Error<E>> for Error<E> {
+    fn from(value: de::Error<E>) -> Self {
+        Error::Decode(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error<io::Error> {
+    fn from(value: io::Error) -> Self {
+        Error::Decode(DeError::ReaderError(value))
+    }
+}
+
+/// Table offset and count read from a footer, checked against the container len.
+fn parse_footer(footer: &[u8; FOOTER_LEN as usize], len: u64) -> Option<(u64, u64)> {
+    let (table_offset, rest) = footer.split_at(8);
+    let (count, magic) = rest.split_at(8);
+    if magic != INDEX_MAGIC {
+        return None;
+    }
+    let table_offset = u64::from_be_bytes(table_offset.try_into().ok()?);
+    let count = u64::from_be_bytes(count.try_into().ok()?);
+    let end = count
+        .checked_mul(8)?
+        .checked_add(table_offset)?
+        .checked_add(FOOTER_LEN)?;
+    (end == len).then_some((table_offset, count))
+}
+
+fn check_bounds<E>(index: usize, len: usize) -> Result<(), Error<E>> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(Error::OutOfBounds { index, len })
+    }
+}
+
+fn check_range<E>(range: &Range<usize>, len: usize) -> Result<(), Error<E>> {
+    if range.start > range.end {
+        Err(Error::OutOfBounds {
+            index: range.start,
+            len: range.end,
+        })
+    } else if range.end > len {
+        Err(Error::OutOfBounds {
+            index: range.end,
+            len,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+const FAILED: &str =
+    "A value of the indexed container failed to be written, the container is corrupt.";
+
+/// Writes values one by one then their offset table.
+///
+/// Nothing is readable before `finish` is called. Once a value fails to be written, part of it may be
+/// written already, so every later `push` and `finish` fail.
+#[cfg(feature = "alloc")]
+pub struct IndexedWriter<W> {
+    serializer: Serializer<W>,
+    offsets: Vec<u64>,
+    position: u64,
+    failed: bool,
+}
+
+#[cfg(feature = "alloc")]
+impl<W: Write> IndexedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        IndexedWriter::from_serializer(Serializer::new(writer))
+    }
+
+    /// Write the values with the configuration of `serializer`, which must not have written anything yet.
+    pub fn from_serializer(serializer: Serializer<W>) -> Self {
+        IndexedWriter {
+            serializer,
+            offsets: Vec::new(),
+            position: 0,
+            failed: false,
+        }
+    }
+
+    pub fn push<T>(&mut self, value: &T) -> ser::Result<(), W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.check_failed()?;
+        // values are read one by one, each one writes its type names in full
+        self.serializer.reset_type_names();
+        let len = value.serialize(&mut self.serializer).inspect_err(|_| {
+            self.failed = true;
+        })?;
+        self.offsets.push(self.position);
+        self.position += len as u64;
+        Ok(())
+    }
+
+    fn check_failed(&self) -> ser::Result<(), W::Error> {
+        if self.failed {
+            return Err(serde::ser::Error::custom(FAILED));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Write the offset table and the footer, and give back the writer, which is not flushed.
+    pub fn finish(mut self) -> ser::Result<W, W::Error> {
+        self.check_failed()?;
+        let writer = self.serializer.get_mut();
+        for offset in &self.offsets {
+            writer.write_bytes(&offset.to_be_bytes())?;
+        }
+        writer.write_bytes(&self.position.to_be_bytes())?;
+        writer.write_bytes(&(self.offsets.len() as u64).to_be_bytes())?;
+        writer.write_bytes(&INDEX_MAGIC)?;
+        Ok(self.serializer.into_inner())
+    }
+}
+
+/// Reads values of an indexed container held in memory, borrowing from it when possible.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexedSlice<'a> {
+    bytes: &'a [u8],
+    table: &'a [u8],
+}
+
+impl<'a> IndexedSlice<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error<EndOfBuff>> {
+        let footer_start = bytes
+            .len()
+            .checked_sub(FOOTER_LEN as usize)
+            .ok_or(Error::InvalidIndex)?;
+        let (values, footer) = bytes.split_at(footer_start);
+        let (table_offset, _) = parse_footer(footer.try_into().unwrap(), bytes.len() as u64)
+            .ok_or(Error::InvalidIndex)?;
+        let (bytes, table) = values.split_at(table_offset as usize);
+        Ok(IndexedSlice { bytes, table })
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len() / 8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    fn offset(&self, index: usize) -> Result<usize, Error<EndOfBuff>> {
+        if index == self.len() {
+            return Ok(self.bytes.len());
+        }
+        let offset = u64::from_be_bytes(self.table[index * 8..][..8].try_into().unwrap());
+        usize::try_from(offset)
+            .ok()
+            .filter(|offset| *offset <= self.bytes.len())
+            .ok_or(Error::InvalidIndex)
+    }
+
+    /// Encoded bytes of the values in `range`.
+    fn range_bytes(&self, range: Range<usize>) -> Result<&'a [u8], Error<EndOfBuff>> {
+        check_range(&range, self.len())?;
+        let start = self.offset(range.start)?;
+        let end = self.offset(range.end)?;
+        self.bytes.get(start..end).ok_or(Error::InvalidIndex)
+    }
+
+    /// Encoded bytes of the value at `index`.
+    pub fn get_bytes(&self, index: usize) -> Result<&'a [u8], Error<EndOfBuff>> {
+        check_bounds(index, self.len())?;
+        self.range_bytes(index..index + 1)
+    }
+
+    pub fn get<T: Deserialize<'a>>(&self, index: usize) -> Result<T, Error<EndOfBuff>> {
+        Ok(crate::from_bytes(self.get_bytes(index)?)?)
+    }
+
+    /// Iterate over the values in `range`, decoding them one after the other.
+    pub fn range<T: Deserialize<'a>>(
+        &self,
+        range: Range<usize>,
+    ) -> Result<RangeIter<'a, &'a [u8], T>, Error<EndOfBuff>> {
+        let remaining = range.len();
+        let bytes = self.range_bytes(range)?;
+        Ok(RangeIter::new(Deserializer::new(bytes), remaining))
+    }
+}
+
+/// Reads values of an indexed container from a seekable reader.
+///
+/// Only the footer is read upfront, each access reads the offsets it needs from the table.
+#[cfg(feature = "std")]
+pub struct IndexedReader<R> {
+    reader: R,
+    table_offset: u64,
+    len: usize,
+}
+
+#[cfg(feature = "std")]
+type TakeReader<'a, R> = IoReader<io::BufReader<io::Take<&'a mut R>>>;
+
+#[cfg(feature = "std")]
+impl<R: io::Read + io::Seek> IndexedReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Error<io::Error>> {
+        let len = reader.seek(io::SeekFrom::End(0))?;
+        if len < FOOTER_LEN {
+            return Err(Error::InvalidIndex);
+        }
+        reader.seek(io::SeekFrom::End(-(FOOTER_LEN as i64)))?;
+        let mut footer = [0; FOOTER_LEN as usize];
+        reader.read_exact(&mut footer)?;
+        let (table_offset, count) = parse_footer(&footer, len).ok_or(Error::InvalidIndex)?;
+        Ok(IndexedReader {
+            reader,
+            table_offset,
+            len: usize::try_from(count).map_err(|_| Error::InvalidIndex)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn offset(&mut self, index: usize) -> Result<u64, Error<io::Error>> {
+        if index == self.len {
+            return Ok(self.table_offset);
+        }
+        self.reader
+            .seek(io::SeekFrom::Start(self.table_offset + index as u64 * 8))?;
+        let mut offset = [0; 8];
+        self.reader.read_exact(&mut offset)?;
+        let offset = u64::from_be_bytes(offset);
+        if offset > self.table_offset {
+            return Err(Error::InvalidIndex);
+        }
+        Ok(offset)
+    }
+
+    /// Deserializer over the encoded values in `range`.
+    fn range_deserializer(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<Deserializer<TakeReader<'_, R>>, Error<io::Error>> {
+        check_range(&range, self.len)?;
+        let start = self.offset(range.start)?;
+        let end = self.offset(range.end)?;
+        let len = end.checked_sub(start).ok_or(Error::InvalidIndex)?;
+        self.reader.seek(io::SeekFrom::Start(start))?;
+        let capacity = len.min(crate::stream::STREAM_CHUNK_SIZE as u64) as usize;
+        let reader = io::BufReader::with_capacity(capacity, io::Read::take(&mut self.reader, len));
+        Ok(Deserializer::new(IoReader::new(reader)))
+    }
+
+    pub fn get<T: DeserializeOwned>(&mut self, index: usize) -> Result<T, Error<io::Error>> {
+        check_bounds(index, self.len)?;
+        let mut deserializer = self.range_deserializer(index..index + 1)?;
+        Ok(T::deserialize(&mut deserializer)?)
+    }
+
+    /// Iterate over the values in `range`, reading them one after the other.
+    pub fn range<T: DeserializeOwned>(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<RangeIter<'static, TakeReader<'_, R>, T>, Error<io::Error>> {
+        let remaining = range.len();
+        let deserializer = self.range_deserializer(range)?;
+        Ok(RangeIter::new(deserializer, remaining))
+    }
+}
+
+/// Iterator over consecutive values of an indexed container.
+pub struct RangeIter<'de, R, T> {
+    deserializer: Deserializer<R>,
+    remaining: usize,
+    value: PhantomData<fn() -> (&'de (), T)>,
+}
+
+impl<R, T> RangeIter<'_, R, T> {
+    fn new(deserializer: Deserializer<R>, remaining: usize) -> Self {
+        RangeIter {
+            deserializer,
+            remaining,
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'de, R: Read<'de>, T: Deserialize<'de>> Iterator for RangeIter<'de, R, T> {
+    type Item = Result<T, Error<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let value = T::deserialize(&mut self.deserializer).map_err(Error::Decode);
+        if value.is_err() {
+            self.remaining = 0;
+        }
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        id: u32,
+        name: String,
+    }
+
+    #[test]
+    fn test_indexed() {
+        let mut writer = IndexedWriter::new(Vec::new());
+        for id in 0..1000 {
+            let name = alloc::format!("event {}", id);
+            writer.push(&Event { id, name }).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let slice = IndexedSlice::new(&bytes).unwrap();
+        assert_eq!(slice.len(), 1000);
+        assert_eq!(slice.get::<Event>(500).unwrap().name, "event 500");
+        assert_eq!(slice.get::<BorrowedEvent>(7).unwrap().name, "event 7");
+        let ids: Vec<u32> = slice
+            .range(997..1000)
+            .unwrap()
+            .map(|event: Result<Event, _>| event.unwrap().id)
+            .collect();
+        assert_eq!(ids, [997, 998, 999]);
+
+        let mut reader = IndexedReader::new(std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.get::<Event>(999).unwrap().id, 999);
+        assert_eq!(reader.get::<Event>(0).unwrap().name, "event 0");
+        let ids: Vec<u32> = reader
+            .range(10..12)
+            .unwrap()
+            .map(|event: Result<Event, _>| event.unwrap().id)
+            .collect();
+        assert_eq!(ids, [10, 11]);
+        assert!(matches!(
+            reader.get::<Event>(1000),
+            Err(Error::OutOfBounds {
+                index: 1000,
+                len: 1000
+            })
+        ));
+
+        assert_eq!(
+            IndexedSlice::new(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::InvalidIndex
+        );
+
+        // a sequence shorter than its len fails after its first element is written.
+        struct Short;
+        impl Serialize for Short {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeSeq;
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element(&1u8)?;
+                seq.end()
+            }
+        }
+        let mut writer = IndexedWriter::new(Vec::new());
+        writer.push(&1u8).unwrap();
+        assert!(writer.push(&Short).is_err());
+        assert!(writer.push(&2u8).is_err());
+        assert!(writer.finish().is_err());
+
+        // every value writes its type names in full, so each one is checked.
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct UserId(u64);
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct OrderId(u64);
+        let mut writer =
+            IndexedWriter::from_serializer(Serializer::new(Vec::new()).with_type_names(true));
+        writer.push(&UserId(1)).unwrap();
+        writer.push(&UserId(2)).unwrap();
+        let bytes = writer.finish().unwrap();
+        let slice = IndexedSlice::new(&bytes).unwrap();
+        assert_eq!(slice.get::<UserId>(1).unwrap(), UserId(2));
+        for index in 0..2 {
+            assert!(matches!(
+                slice.get::<OrderId>(index),
+                Err(Error::Decode(de::Error::UnexpectedTypeName { .. }))
+            ));
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct BorrowedEvent<'a> {
+        #[allow(dead_code)]
+        id: u32,
+        name: &'a str,
+    }
+}
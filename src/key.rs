@@ -0,0 +1,813 @@
+//! Order preserving encoding for keys of sorted key-value stores.
+//!
+//! Unlike the regular format it is not self describing, but comparing encoded keys byte-wise gives the same
+//! order as comparing the values with their `Ord` implementation, as derived by `#[derive(Ord)]`:
+//! - unsigned integers are big-endian, signed integers are big-endian with the sign bit flipped,
+//! - floats are ordered like `f32::total_cmp` and `f64::total_cmp`,
+//! - strings and byte arrays have their `0x00` bytes escaped as `0x00 0xFF` and are terminated by `0x00 0x01`,
+//! - `None` is `0x00` and `Some` is `0x01` followed by the value,
+//! - elements of sequences and entries of maps are prefixed by `0x01`, and the end is marked by `0x00`,
+//! - tuples and structs are their fields one after the other, and enum variants start with their index as a `u32`.
+
+use alloc::{borrow::Cow, vec::Vec};
+use serde::{
+    de::{self, IntoDeserializer, Visitor},
+    ser, Deserialize, Serialize,
+};
+
+use crate::error::{EndOfBuff, NoRWError};
+
+pub type SerError = crate::ser::Error<NoRWError>;
+pub type DeError = crate::de::Error<EndOfBuff>;
+
+const ESCAPE: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xFF;
+const TERMINATOR: u8 = 0x01;
+const END: u8 = 0x00;
+const ELEMENT: u8 = 0x01;
+
+/// Encode `value` so that keys sort byte-wise like the values.
+pub fn to_key_bytes<T>(value: &T) -> Result<Vec<u8>, SerError>
+where
+    T: ?Sized + Serialize,
+{
+    let mut serializer = KeySerializer { output: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Decode a key encoded with `to_key_bytes`, every byte must be consumed.
+pub fn from_key_bytes<'de, T>(bytes: &'de [u8]) -> Result<T, DeError>
+where
+    T: Deserialize<'de>,
+{
+    let mut deserializer = KeyDeserializer { input: bytes };
+    let value = T::deserialize(&mut deserializer)?;
+    if !deserializer.input.is_empty() {
+        return Err(de::Error::custom("trailing bytes after the key"));
+    }
+    Ok(value)
+}
+
+pub struct KeySerializer {
+    output: Vec<u8>,
+}
+
+impl KeySerializer {
+    fn write_escaped(&mut self, bytes: &[u8]) {
+        for chunk in bytes.split_inclusive(|byte| *byte == ESCAPE) {
+            self.output.extend_from_slice(chunk);
+            if chunk.last() == Some(&ESCAPE) {
+                self.output.push(ESCAPED_ZERO);
+            }
+        }
+        self.output.extend_from_slice(&[ESCAPE, TERMINATOR]);
+    }
+}
+
+macro_rules! serialize_unsigned {
+    ($fn_name:ident, $t:ty) => {
+        fn $fn_name(self, v: $t) -> Result<(), SerError> {
+            self.output.extend_from_slice(&v.to_be_bytes());
+            Ok(())
+        }
+    };
+}
+
+macro_rules! serialize_signed {
+    ($fn_name:ident, $t:ty, $unsigned:ty) => {
+        fn $fn_name(self, v: $t) -> Result<(), SerError> {
+            // flipping the sign bit puts negative numbers before positive ones.
+            let v = (v as $unsigned) ^ (1 << (<$unsigned>::BITS - 1));
+            self.output.extend_from_slice(&v.to_be_bytes());
+            Ok(())
+        }
+    };
+}
+
+macro_rules! serialize_float {
+    ($fn_name:ident, $t:ty, $signed:ty, $unsigned:ty, $forward_fn:ident) => {
+        fn $fn_name(self, v: $t) -> Result<(), SerError> {
+            // same transformation as `total_cmp`, the bits are then ordered as a signed integer.
+            let bits = v.to_bits() as $signed;
+            let bits = bits ^ ((((bits >> (<$signed>::BITS - 1)) as $unsigned) >> 1) as $signed);
+            self.$forward_fn(bits)
+        }
+    };
+}
+
+impl ser::Serializer for &mut KeySerializer {
+    type Ok = ();
+    type Error = SerError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), SerError> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    serialize_signed!(serialize_i8, i8, u8);
+    serialize_signed!(serialize_i16, i16, u16);
+    serialize_signed!(serialize_i32, i32, u32);
+    serialize_signed!(serialize_i64, i64, u64);
+    serialize_signed!(serialize_i128, i128, u128);
+    serialize_unsigned!(serialize_u8, u8);
+    serialize_unsigned!(serialize_u16, u16);
+    serialize_unsigned!(serialize_u32, u32);
+    serialize_unsigned!(serialize_u64, u64);
+    serialize_unsigned!(serialize_u128, u128);
+    serialize_float!(serialize_f32, f32, i32, u32, serialize_i32);
+    serialize_float!(serialize_f64, f64, i64, u64, serialize_i64);
+
+    fn serialize_char(self, v: char) -> Result<(), SerError> {
+        self.serialize_u32(v.into())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SerError> {
+        self.write_escaped(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SerError> {
+        self.write_escaped(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), SerError> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<(), SerError>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SerError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), SerError> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), SerError>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), SerError>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerError> {
+        Ok(self)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for &mut KeySerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerError>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.output.push(ELEMENT);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerError> {
+        self.output.push(END);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut KeySerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), SerError>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.output.push(ELEMENT);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), SerError>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerError> {
+        self.output.push(END);
+        Ok(())
+    }
+}
+
+macro_rules! implement_fields {
+    ($trait:ident, $fn_name:ident $(, $key:ident)?) => {
+        impl<'a> ser::$trait for &'a mut KeySerializer {
+            type Ok = ();
+            type Error = SerError;
+
+            fn $fn_name<T>(&mut self, $($key: &'static str,)? value: &T) -> Result<(), SerError>
+            where
+                T: ?Sized + Serialize,
+            {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<(), SerError> {
+                Ok(())
+            }
+        }
+    };
+}
+
+implement_fields!(SerializeTuple, serialize_element);
+implement_fields!(SerializeTupleStruct, serialize_field);
+implement_fields!(SerializeTupleVariant, serialize_field);
+implement_fields!(SerializeStruct, serialize_field, _key);
+implement_fields!(SerializeStructVariant, serialize_field, _key);
+
+pub struct KeyDeserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> KeyDeserializer<'de> {
+    fn pop_n<const N: usize>(&mut self) -> Result<[u8; N], DeError> {
+        if self.input.len() < N {
+            return Err(DeError::ReaderError(EndOfBuff));
+        }
+        let (bytes, rest) = self.input.split_at(N);
+        self.input = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn pop_byte(&mut self) -> Result<u8, DeError> {
+        self.pop_n::<1>().map(|[byte]| byte)
+    }
+
+    /// Whether another element follows in a sequence or a map.
+    fn pop_element_marker(&mut self) -> Result<bool, DeError> {
+        match self.pop_byte()? {
+            ELEMENT => Ok(true),
+            END => Ok(false),
+            byte => Err(de::Error::custom(format_args!(
+                "invalid sequence marker {:#04x}",
+                byte
+            ))),
+        }
+    }
+
+    /// Bytes of an escaped string, borrowed when nothing was escaped.
+    fn pop_escaped(&mut self) -> Result<Cow<'de, [u8]>, DeError> {
+        let mut unescaped: Option<Vec<u8>> = None;
+        let mut pos = 0;
+        loop {
+            let zero = memchr::memchr(ESCAPE, &self.input[pos..])
+                .ok_or(DeError::ReaderError(EndOfBuff))?
+                + pos;
+            let next = *self
+                .input
+                .get(zero + 1)
+                .ok_or(DeError::ReaderError(EndOfBuff))?;
+            match next {
+                TERMINATOR => {
+                    let (bytes, rest) = (&self.input[..zero], &self.input[zero + 2..]);
+                    let result = match unescaped {
+                        None => Cow::Borrowed(bytes),
+                        Some(mut unescaped) => {
+                            unescaped.extend_from_slice(&bytes[pos..]);
+                            Cow::Owned(unescaped)
+                        }
+                    };
+                    self.input = rest;
+                    return Ok(result);
+                }
+                ESCAPED_ZERO => {
+                    let unescaped = unescaped.get_or_insert_with(Vec::new);
+                    unescaped.extend_from_slice(&self.input[pos..=zero]);
+                    pos = zero + 2;
+                }
+                byte => {
+                    return Err(de::Error::custom(format_args!(
+                        "invalid escaped byte {:#04x}",
+                        byte
+                    )))
+                }
+            }
+        }
+    }
+}
+
+macro_rules! deserialize_unsigned {
+    ($fn_name:ident, $t:ty, $visit_fn:ident) => {
+        fn $fn_name<V>(self, visitor: V) -> Result<V::Value, DeError>
+        where
+            V: Visitor<'de>,
+        {
+            visitor.$visit_fn(<$t>::from_be_bytes(self.pop_n()?))
+        }
+    };
+}
+
+macro_rules! deserialize_signed {
+    ($fn_name:ident, $t:ty, $unsigned:ty, $visit_fn:ident) => {
+        fn $fn_name<V>(self, visitor: V) -> Result<V::Value, DeError>
+        where
+            V: Visitor<'de>,
+        {
+            let v = <$unsigned>::from_be_bytes(self.pop_n()?) ^ (1 << (<$unsigned>::BITS - 1));
+            visitor.$visit_fn(v as $t)
+        }
+    };
+}
+
+macro_rules! deserialize_float {
+    ($fn_name:ident, $t:ty, $unsigned:ty, $visit_fn:ident) => {
+        fn $fn_name<V>(self, visitor: V) -> Result<V::Value, DeError>
+        where
+            V: Visitor<'de>,
+        {
+            let bits = <$unsigned>::from_be_bytes(self.pop_n()?) ^ (1 << (<$unsigned>::BITS - 1));
+            // negative numbers had every bit but the sign flipped.
+            let bits = if bits >> (<$unsigned>::BITS - 1) == 1 {
+                bits ^ (<$unsigned>::MAX >> 1)
+            } else {
+                bits
+            };
+            visitor.$visit_fn(<$t>::from_bits(bits))
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &mut KeyDeserializer<'de> {
+    type Error = DeError;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom(
+            "The key encoding is not self describing, deserialize_any is not supported.",
+        ))
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.pop_byte()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            byte => Err(de::Error::invalid_value(
+                de::Unexpected::Unsigned(byte.into()),
+                &"a boolean",
+            )),
+        }
+    }
+
+    deserialize_signed!(deserialize_i8, i8, u8, visit_i8);
+    deserialize_signed!(deserialize_i16, i16, u16, visit_i16);
+    deserialize_signed!(deserialize_i32, i32, u32, visit_i32);
+    deserialize_signed!(deserialize_i64, i64, u64, visit_i64);
+    deserialize_signed!(deserialize_i128, i128, u128, visit_i128);
+    deserialize_unsigned!(deserialize_u8, u8, visit_u8);
+    deserialize_unsigned!(deserialize_u16, u16, visit_u16);
+    deserialize_unsigned!(deserialize_u32, u32, visit_u32);
+    deserialize_unsigned!(deserialize_u64, u64, visit_u64);
+    deserialize_unsigned!(deserialize_u128, u128, visit_u128);
+    deserialize_float!(deserialize_f32, f32, u32, visit_f32);
+    deserialize_float!(deserialize_f64, f64, u64, visit_f64);
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        let v = u32::from_be_bytes(self.pop_n()?);
+        let c = char::from_u32(v).ok_or_else(|| {
+            <DeError as de::Error>::invalid_value(de::Unexpected::Unsigned(v.into()), &"a char")
+        })?;
+        visitor.visit_char(c)
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.pop_escaped()? {
+            Cow::Borrowed(bytes) => {
+                visitor.visit_borrowed_str(core::str::from_utf8(bytes).map_err(DeError::Utf8Error)?)
+            }
+            Cow::Owned(bytes) => visitor.visit_string(
+                alloc::string::String::from_utf8(bytes)
+                    .map_err(|err| DeError::Utf8Error(err.utf8_error()))?,
+            ),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.pop_escaped()? {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.pop_byte()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            byte => Err(de::Error::invalid_value(
+                de::Unexpected::Unsigned(byte.into()),
+                &"an option",
+            )),
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Elements { de: self })
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Fields {
+            de: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(Elements { de: self })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Elements of sequences and entries of maps, each prefixed by a marker.
+struct Elements<'a, 'de> {
+    de: &'a mut KeyDeserializer<'de>,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = DeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, DeError>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.de.pop_element_marker()? {
+            seed.deserialize(&mut *self.de).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Elements<'_, 'de> {
+    type Error = DeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, DeError>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        de::SeqAccess::next_element_seed(self, seed)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, DeError>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+/// Fields of tuples and structs, their count is known from the type.
+struct Fields<'a, 'de> {
+    de: &'a mut KeyDeserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Fields<'_, 'de> {
+    type Error = DeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, DeError>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut KeyDeserializer<'de> {
+    type Error = DeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), DeError>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let index = u32::from_be_bytes(self.pop_n()?);
+        let value = seed.deserialize(IntoDeserializer::<DeError>::into_deserializer(index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut KeyDeserializer<'de> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> Result<(), DeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, DeError>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+    enum Shard {
+        Local,
+        Remote { region: String, id: u16 },
+    }
+
+    type Key = (i64, String, Option<u8>, Vec<u16>, Shard);
+
+    #[test]
+    fn test_key_order() {
+        let mut keys: Vec<Key> = Vec::new();
+        for number in [i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX] {
+            for string in ["", "\0", "a", "a\0", "a\0b", "ab", "b"] {
+                for option in [None, Some(0), Some(7)] {
+                    for seq in [vec![], vec![0], vec![0, 1], vec![1]] {
+                        for shard in [
+                            Shard::Local,
+                            Shard::Remote {
+                                region: "eu".into(),
+                                id: 3,
+                            },
+                        ] {
+                            keys.push((number, string.into(), option, seq.clone(), shard));
+                        }
+                    }
+                }
+            }
+        }
+        keys.reverse();
+        let mut encoded: Vec<Vec<u8>> = keys.iter().map(|key| to_key_bytes(key).unwrap()).collect();
+        keys.sort();
+        encoded.sort();
+        let decoded: Vec<Key> = encoded
+            .iter()
+            .map(|bytes| from_key_bytes(bytes).unwrap())
+            .collect();
+        assert_eq!(decoded, keys);
+    }
+
+    #[test]
+    fn test_float_order() {
+        let floats = [
+            f64::NEG_INFINITY,
+            -1e10,
+            -1.5,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            2.5,
+            f64::INFINITY,
+        ];
+        let encoded: Vec<Vec<u8>> = floats.iter().map(|f| to_key_bytes(f).unwrap()).collect();
+        assert!(encoded.windows(2).all(|pair| pair[0] < pair[1]));
+        for (float, bytes) in floats.iter().zip(&encoded) {
+            let decoded: f64 = from_key_bytes(bytes).unwrap();
+            assert_eq!(decoded.to_bits(), float.to_bits());
+        }
+    }
+}
@@ -4,17 +4,57 @@
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
+#[cfg(feature = "alloc")]
+mod canonical;
+#[cfg(feature = "std")]
+pub mod compress;
 pub mod de;
+#[cfg(feature = "diff")]
+pub mod diff;
 pub mod error;
+pub mod indexed;
+#[cfg(feature = "alloc")]
+pub mod key;
+#[cfg(feature = "std")]
+pub mod log;
+#[cfg(feature = "diff")]
+pub mod patch;
+pub mod query;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "sealed")]
+pub mod sealed;
 pub mod ser;
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "signed")]
+pub mod signed;
+pub mod stream;
 mod tag;
 mod utils;
+#[cfg(feature = "alloc")]
+pub mod value;
 
-pub use de::{from_bytes, from_reader, Deserializer};
+#[cfg(feature = "alloc")]
+pub use canonical::is_canonical;
+#[cfg(feature = "std")]
+pub use compress::{from_reader_compressed, to_writer_compressed};
+#[cfg(feature = "std")]
+pub use de::from_io_reader;
+pub use de::{from_bytes, from_bytes_in_place, from_bytes_strict, from_reader, Deserializer};
+#[cfg(feature = "diff")]
+pub use diff::diff;
 pub use error::{DeError, NoRWError, SerError};
 #[cfg(feature = "alloc")]
-pub use ser::to_bytes;
-pub use ser::{get_serialized_size, to_buff, to_writer, Serializer};
+pub use key::{from_key_bytes, to_key_bytes};
+pub use query::query;
+pub use ser::{get_serialized_size, to_buff, to_writer, Serializer, VariantEncoding};
+#[cfg(feature = "alloc")]
+pub use ser::{to_bytes, to_canonical_bytes, to_vec_into};
+#[cfg(feature = "std")]
+pub use shared::Shared;
 
 pub use utils::read;
 pub use utils::write;
@@ -30,6 +70,7 @@ mod test {
 
     pub type Result<T = (), E = CheckError> = core::result::Result<T, E>;
 
+    #[allow(unused)]
     #[derive(Deserialize, Serialize)]
     enum Test {
         Unit,
@@ -62,4 +103,459 @@ mod test {
             },
         )
     }
+
+    #[test]
+    fn test_from_reader_slice_borrows() {
+        let bytes = crate::to_bytes(&("borrowed", 5u8)).unwrap();
+        let (s, n): (&str, u8) = crate::from_reader(&bytes[..]).unwrap();
+        assert_eq!((s, n), ("borrowed", 5));
+    }
+
+    #[test]
+    fn test_variant_len() {
+        use crate::tag::Tag;
+        let bytes = crate::to_bytes(&Test::Tuple(1, 2)).unwrap();
+        let (tag, u8_tag) = (Tag::TupleVariant as u8, Tag::U8 as u8);
+        assert_eq!(bytes, [tag, u8_tag, 3, u8_tag, 2, u8_tag, 1, u8_tag, 2]);
+        assert!(matches!(crate::from_bytes(&bytes), Ok(Test::Tuple(1, 2))));
+
+        // variants used to be written without their len, their first field can't be read as one.
+        let old = [tag, u8_tag, 3, u8_tag, 1, u8_tag, 2];
+        assert!(crate::from_bytes::<Test>(&old).is_err());
+        let field = crate::to_bytes("field").unwrap();
+        let old = [
+            &[Tag::StructVariant as u8, u8_tag, 2],
+            &field[..],
+            &[u8_tag, 1],
+        ]
+        .concat();
+        assert!(crate::from_bytes::<Test>(&old).is_err());
+    }
+
+    #[test]
+    fn test_compact_numbers() {
+        let value = (70_000u32, -70_000i32, u64::MAX, i64::MIN, 300u16);
+        let bytes = crate::to_bytes(&value).unwrap();
+        let deserialized: (u32, i32, u64, i64, u16) = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized, value);
+    }
+
+    #[test]
+    fn test_from_io_reader() {
+        let value = (String::from("copied"), vec![1u8, 2, 3], 'c');
+        let bytes = crate::to_bytes(&value).unwrap();
+        let reader = crate::read::IoReader::new(std::io::BufReader::with_capacity(3, &bytes[..]));
+        let deserialized: (String, Vec<u8>, char) = crate::from_reader(reader).unwrap();
+        assert_eq!(value, deserialized);
+        let deserialized: (String, Vec<u8>, char) = crate::from_io_reader(&bytes[..]).unwrap();
+        assert_eq!(value, deserialized);
+    }
+
+    #[test]
+    fn test_chunked() {
+        let mut bytes = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut bytes);
+        let mut chunked = serializer.serialize_chunked_str().unwrap();
+        // split in the middle of the 'é'
+        chunked.write_chunk(&"chunk é".as_bytes()[..7]).unwrap();
+        chunked.write_chunk(&"chunk é".as_bytes()[7..]).unwrap();
+        chunked.write_chunk(b"").unwrap();
+        chunked.finish().unwrap();
+        let mut chunked = serializer.serialize_chunked_bytes().unwrap();
+        chunked.write_chunk(&[1, 2, 3]).unwrap();
+        chunked.finish().unwrap();
+
+        let mut reader = &bytes[..];
+        let string: String = crate::from_reader(&mut reader).unwrap();
+        let bytes_ref: &serde_bytes::Bytes = crate::from_reader(&mut reader).unwrap();
+        assert_eq!(string, "chunk é");
+        assert_eq!(bytes_ref.as_ref(), [1, 2, 3]);
+
+        let mut reader = crate::read::IoReader::new(&bytes[..]);
+        let string: String = crate::from_reader(&mut reader).unwrap();
+        let bytes_buf: serde_bytes::ByteBuf = crate::from_reader(&mut reader).unwrap();
+        assert_eq!(string, "chunk é");
+        assert_eq!(bytes_buf.as_ref(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_variant_encoding() {
+        use crate::VariantEncoding;
+
+        #[derive(Debug, PartialEq, Serialize)]
+        enum Before {
+            First,
+            Second(u8),
+            Third { field: String },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum After {
+            Third {
+                field: String,
+            },
+            #[serde(alias = "Second")]
+            Renamed(u8),
+            First,
+        }
+
+        let values = [
+            (Before::First, After::First),
+            (Before::Second(3), After::Renamed(3)),
+            (
+                Before::Third {
+                    field: "value".into(),
+                },
+                After::Third {
+                    field: "value".into(),
+                },
+            ),
+        ];
+
+        for encoding in [VariantEncoding::Name, VariantEncoding::NameAndIndex] {
+            for (before, after) in &values {
+                let mut bytes = Vec::new();
+                let mut serializer =
+                    crate::Serializer::new(&mut bytes).with_variant_encoding(encoding);
+                before.serialize(&mut serializer).unwrap();
+                let decoded: After = crate::from_bytes(&bytes).unwrap();
+                assert_eq!(&decoded, after);
+            }
+        }
+    }
+
+    #[test]
+    fn test_type_names() {
+        use crate::{de::Error, error::UnexpectedTag, tag::Tag, value::Value};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct UserId(u64);
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct OrderId(u64);
+
+        let ids = vec![UserId(5), UserId(6)];
+        let mut bytes = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut bytes).with_type_names(true);
+        ids.serialize(&mut serializer).unwrap();
+        // the second name is interned.
+        assert_eq!(bytes.windows(6).filter(|w| w == b"UserId").count(), 1);
+
+        assert_eq!(crate::from_bytes::<Vec<UserId>>(&bytes).unwrap(), ids);
+        assert!(matches!(
+            crate::from_bytes::<Vec<OrderId>>(&bytes),
+            Err(Error::UnexpectedTypeName {
+                expected: "OrderId",
+                ..
+            })
+        ));
+        // names are skipped when not checked.
+        assert_eq!(
+            Value::from_bytes(&bytes).unwrap(),
+            Value::from_bytes(&crate::to_bytes(&ids).unwrap()).unwrap()
+        );
+
+        let mut canonical = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut canonical)
+            .with_canonical(true)
+            .with_type_names(true);
+        Some(Test::NewType(1)).serialize(&mut serializer).unwrap();
+        assert!(crate::is_canonical(&canonical));
+        assert!(crate::from_bytes::<Option<MyStruct>>(&canonical).is_err());
+
+        // an index refers to a name read before.
+        let mut forged = vec![Tag::TypeName as u8, Tag::U8 as u8, 3];
+        forged.extend(crate::to_bytes(&OrderId(1)).unwrap());
+        assert_eq!(
+            crate::from_bytes::<OrderId>(&forged),
+            Err(Error::UnknownTypeName(3))
+        );
+
+        // type names can't be nested, in the name nor in its len.
+        let nested = vec![Tag::TypeName as u8; 2_000_000];
+        assert!(matches!(
+            crate::from_bytes::<u64>(&nested),
+            Err(Error::UnexpectedTag(UnexpectedTag {
+                got: Tag::TypeName,
+                ..
+            }))
+        ));
+        let nested = [Tag::TypeName as u8, Tag::String as u8, Tag::TypeName as u8];
+        assert!(matches!(
+            crate::from_bytes::<u64>(&nested),
+            Err(Error::UnexpectedTag(UnexpectedTag {
+                got: Tag::TypeName,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_len_mismatch() {
+        use crate::ser::Error;
+        use serde::ser::{SerializeMap, SerializeSeq, Serializer as _};
+
+        struct Lying {
+            declared: usize,
+            written: usize,
+            map: bool,
+        }
+
+        impl Serialize for Lying {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                if self.map {
+                    let mut map = serializer.serialize_map(Some(self.declared))?;
+                    (0..self.written).try_for_each(|i| map.serialize_entry(&i, &i))?;
+                    return map.end();
+                }
+                let mut seq = serializer.serialize_seq(Some(self.declared))?;
+                (0..self.written).try_for_each(|i| seq.serialize_element(&i))?;
+                seq.end()
+            }
+        }
+
+        for map in [false, true] {
+            let value = |declared, written| Lying {
+                declared,
+                written,
+                map,
+            };
+            assert!(crate::to_bytes(&value(3, 3)).is_ok());
+            assert!(matches!(
+                crate::to_bytes(&value(3, 4)),
+                Err(Error::LenMismatch {
+                    declared: 3,
+                    written: 4
+                })
+            ));
+            assert!(matches!(
+                crate::to_bytes(&value(2, 1)),
+                Err(Error::LenMismatch {
+                    declared: 2,
+                    written: 1
+                })
+            ));
+        }
+
+        // the len written by canonical encoding is counted, not declared.
+        let mut bytes = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut bytes).with_canonical(true);
+        let mut map = (&mut serializer).serialize_map(Some(5)).unwrap();
+        map.serialize_entry(&1u8, &2u8).unwrap();
+        SerializeMap::end(map).unwrap();
+        let decoded: std::collections::BTreeMap<u8, u8> = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 1);
+    }
+
+    #[test]
+    fn test_buffer_reuse() {
+        let mut buffer = Vec::new();
+        let first = (String::from("first"), vec![1u32, 2, 3]);
+        let len = crate::to_vec_into(&first, &mut buffer).unwrap();
+        assert_eq!(len, buffer.len());
+        let capacity = buffer.capacity();
+        crate::to_vec_into(&(String::from("second"), vec![4u32]), &mut buffer).unwrap();
+        assert_eq!(buffer.capacity(), capacity);
+
+        let mut place = (String::with_capacity(64), Vec::with_capacity(64));
+        let (string_ptr, vec_ptr) = (place.0.as_ptr(), place.1.as_ptr());
+        crate::from_bytes_in_place(&buffer, &mut place).unwrap();
+        assert_eq!(place, (String::from("second"), vec![4u32]));
+        assert_eq!((place.0.as_ptr(), place.1.as_ptr()), (string_ptr, vec_ptr));
+    }
+
+    #[test]
+    fn test_buffered_enums() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum Shape {
+            Point,
+            Circle(u32),
+            Rect(u32, u32),
+            Polygon { sides: u8 },
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(untagged)]
+        enum Untagged {
+            Shape(Shape),
+            Number(u64),
+            Text(String),
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(tag = "kind")]
+        enum Command {
+            Draw { shape: Shape },
+            Clear,
+        }
+
+        #[derive(Serialize)]
+        struct Full {
+            id: u32,
+            color: String,
+            shape: Shape,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Partial {
+            id: u32,
+            #[serde(flatten)]
+            extra: BTreeMap<String, Untagged>,
+        }
+
+        let shapes = [
+            Shape::Point,
+            Shape::Circle(3),
+            Shape::Rect(4, 5),
+            Shape::Polygon { sides: 6 },
+        ];
+        for shape in shapes.iter().cloned() {
+            let untagged = Untagged::Shape(shape.clone());
+            let bytes = crate::to_bytes(&untagged).unwrap();
+            assert_eq!(crate::from_bytes::<Untagged>(&bytes).unwrap(), untagged);
+
+            let command = Command::Draw { shape };
+            let bytes = crate::to_bytes(&command).unwrap();
+            assert_eq!(crate::from_bytes::<Command>(&bytes).unwrap(), command);
+        }
+        let bytes = crate::to_bytes(&Untagged::Text("text".into())).unwrap();
+        assert_eq!(
+            crate::from_bytes::<Untagged>(&bytes).unwrap(),
+            Untagged::Text("text".into())
+        );
+        let bytes = crate::to_bytes(&Command::Clear).unwrap();
+        assert_eq!(
+            crate::from_bytes::<Command>(&bytes).unwrap(),
+            Command::Clear
+        );
+
+        let full = Full {
+            id: 1,
+            color: "red".into(),
+            shape: Shape::Rect(4, 5),
+        };
+        let bytes = crate::to_bytes(&full).unwrap();
+        let partial: Partial = crate::from_bytes(&bytes).unwrap();
+        let expected = Partial {
+            id: 1,
+            extra: BTreeMap::from([
+                ("color".into(), Untagged::Text("red".into())),
+                ("shape".into(), Untagged::Shape(Shape::Rect(4, 5))),
+            ]),
+        };
+        assert_eq!(partial, expected);
+        let bytes = crate::to_bytes(&partial).unwrap();
+        assert_eq!(crate::from_bytes::<Partial>(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_strict() {
+        use crate::{from_bytes_strict, tag::Tag, DeError};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Entry {
+            id: u64,
+            tags: BTreeMap<String, i32>,
+            pair: (u8, i128),
+        }
+
+        let entry = Entry {
+            id: 300,
+            tags: BTreeMap::from([("a".into(), -1), ("b".into(), 70000)]),
+            pair: (1, -2),
+        };
+        let bytes = crate::to_canonical_bytes(&entry).unwrap();
+        assert_eq!(from_bytes_strict::<Entry>(&bytes).unwrap(), entry);
+
+        let err = from_bytes_strict::<u16>(&[Tag::U16 as u8, 0, 5]).unwrap_err();
+        assert_eq!(err, DeError::NonMinimal(Tag::U16));
+        assert_eq!(
+            crate::from_bytes::<u16>(&[Tag::U16 as u8, 0, 5]).unwrap(),
+            5
+        );
+
+        let bytes = crate::to_canonical_bytes(&entry.tags).unwrap();
+        assert!(crate::from_bytes::<Entry>(&bytes).is_err());
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Tags {
+            a: i32,
+            b: i32,
+        }
+        assert!(crate::from_bytes::<Tags>(&bytes).is_ok());
+        assert!(matches!(
+            from_bytes_strict::<Tags>(&bytes),
+            Err(DeError::UnexpectedTag(_))
+        ));
+
+        let bytes = crate::to_canonical_bytes(&(1u8, 2u8, 3u8)).unwrap();
+        assert!(crate::from_bytes::<(u8, u8)>(&bytes).is_ok());
+        assert!(matches!(
+            from_bytes_strict::<(u8, u8)>(&bytes),
+            Err(DeError::InvalidLength { len: 3, .. })
+        ));
+
+        struct Pairs<'a>(&'a [(&'a str, u8)]);
+        impl Serialize for Pairs<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+            }
+        }
+        let bytes = crate::to_bytes(&Pairs(&[("a", 1), ("b", 2), ("b", 3)])).unwrap();
+        assert!(crate::from_bytes::<BTreeMap<String, u8>>(&bytes).is_ok());
+        assert_eq!(
+            from_bytes_strict::<BTreeMap<String, u8>>(&bytes),
+            Err(DeError::DuplicateKey)
+        );
+        let bytes = crate::to_bytes(&Pairs(&[("b", 1), ("a", 2)])).unwrap();
+        assert!(crate::from_bytes::<BTreeMap<String, u8>>(&bytes).is_ok());
+        assert_eq!(
+            from_bytes_strict::<BTreeMap<String, u8>>(&bytes),
+            Err(DeError::UnsortedKeys)
+        );
+        // keys are only recorded, and checked, by a `Recorder`.
+        let mut de = crate::Deserializer::new(&bytes[..]).with_strict(true);
+        assert!(BTreeMap::<String, u8>::deserialize(&mut de).is_ok());
+        let mut de =
+            crate::Deserializer::new(crate::read::Recorder::new(&bytes[..])).with_strict(true);
+        assert_eq!(
+            BTreeMap::<String, u8>::deserialize(&mut de),
+            Err(DeError::UnsortedKeys)
+        );
+
+        // the same text written as a string and as a marker terminated string
+        let mut bytes = vec![Tag::Map as u8, Tag::U8 as u8, 2];
+        bytes.extend(crate::to_bytes("a").unwrap());
+        bytes.extend([Tag::U8 as u8, 1, Tag::MarkerTerminatedString as u8, b'a']);
+        bytes.extend(crate::tag::UNSIZED_STRING_END_MARKER);
+        bytes.extend([Tag::U8 as u8, 2]);
+        let map = crate::from_bytes::<std::collections::HashMap<String, u8>>(&bytes).unwrap();
+        assert_eq!(map, [(String::from("a"), 2)].into());
+        assert!(matches!(
+            from_bytes_strict::<std::collections::HashMap<String, u8>>(&bytes),
+            Err(DeError::UnexpectedTag(_))
+        ));
+
+        let mut bytes = vec![Tag::UnsizedMap as u8];
+        bytes.extend(crate::to_bytes("a").unwrap());
+        bytes.extend([Tag::U8 as u8, 1, Tag::UnsizedSeqEnd as u8]);
+        assert!(crate::from_bytes::<BTreeMap<String, u8>>(&bytes).is_ok());
+        assert!(matches!(
+            from_bytes_strict::<BTreeMap<String, u8>>(&bytes),
+            Err(DeError::UnexpectedTag(_))
+        ));
+
+        let mut bytes = crate::to_canonical_bytes(&1u8).unwrap();
+        bytes.push(0);
+        assert_eq!(from_bytes_strict::<u8>(&bytes), Err(DeError::TrailingBytes));
+        let mut de = crate::Deserializer::new(&bytes[..]).with_strict(true);
+        assert_eq!(u8::deserialize(&mut de), Ok(1));
+        assert_eq!(de.end(), Err(DeError::TrailingBytes));
+        let mut de = crate::Deserializer::new(&bytes[..bytes.len() - 1]).with_strict(true);
+        assert_eq!(u8::deserialize(&mut de), Ok(1));
+        assert_eq!(de.end(), Ok(()));
+    }
 }
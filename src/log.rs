@@ -0,0 +1,375 @@
+//! Append-only files of values, recoverable after a crash.
+//!
+//! Each record is framed by its length and a CRC-32 of its content:
+//!
+//! | len: u32 BE | crc: u32 BE | rsbin value of `len` bytes |
+//!
+//! A crash in the middle of an append leaves an incomplete last record, which readers report as torn
+//! and `AppendLog::open` removes. A record that doesn't match its checksum is only removed, with the
+//! records after it, by `recover`.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+use core::marker::PhantomData;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+use crate::{de, error::EndOfBuff, ser, Serializer};
+
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialize(ser::Error<io::Error>),
+    Deserialize(de::Error<EndOfBuff>),
+    /// The record starting at this offset doesn't match its checksum.
+    Checksum(u64),
+    /// The encoded value doesn't fit in a record.
+    TooLarge(usize),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => Display::fmt(err, f),
+            Error::Serialize(err) => Display::fmt(err, f),
+            Error::Deserialize(err) => Display::fmt(err, f),
+            Error::Checksum(offset) => f.write_fmt(format_args!(
+                "The record at offset {} doesn't match its checksum.",
+                offset
+            )),
+            Error::TooLarge(len) => f.write_fmt(format_args!(
+                "A record can't hold a value of {} bytes.",
+                len
+            )),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<ser::Error<io::Error>> for Error {
+    fn from(value: ser::Error<io::Error>) -> Self {
+        Error::Serialize(value)
+    }
+}
+
+impl From<de::Error<EndOfBuff>> for Error {
+    fn from(value: de::Error<EndOfBuff>) -> Self {
+        Error::Deserialize(value)
+    }
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// CRC-32 (IEEE) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Appends values to a log file.
+///
+/// Each value is written with a single `write` call, flushed to the OS but not synced to the disk,
+/// see `sync`.
+pub struct AppendLog<T: ?Sized> {
+    file: File,
+    buffer: Vec<u8>,
+    len: u64,
+    value: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized + Serialize> AppendLog<T> {
+    /// Open or create the log at `path`, removing a torn last record left by a crash.
+    ///
+    /// Fails with `Checksum` if a record is corrupted, `recover` then removes it and the records after it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = recover_file(&mut file, false)?;
+        file.seek(io::SeekFrom::Start(len))?;
+        Ok(AppendLog {
+            file,
+            buffer: Vec::new(),
+            len,
+            value: PhantomData,
+        })
+    }
+
+    /// Append `value`, returning the offset of its record.
+    pub fn append(&mut self, value: &T) -> Result<u64, Error> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&[0; HEADER_LEN]);
+        let len = Serializer::to_writer(value, &mut self.buffer)?;
+        let len32 = u32::try_from(len).map_err(|_| Error::TooLarge(len))?;
+        let crc = crc32(&self.buffer[HEADER_LEN..]);
+        self.buffer[..4].copy_from_slice(&len32.to_be_bytes());
+        self.buffer[4..HEADER_LEN].copy_from_slice(&crc.to_be_bytes());
+        if let Err(err) = self.file.write_all(&self.buffer) {
+            // remove the part of the record written, so later appends follow the last complete one.
+            self.file.set_len(self.len)?;
+            self.file.seek(io::SeekFrom::Start(self.len))?;
+            return Err(err.into());
+        }
+        let offset = self.len;
+        self.len += self.buffer.len() as u64;
+        Ok(offset)
+    }
+
+    /// Make the appended records durable.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        Ok(self.file.sync_data()?)
+    }
+
+    /// Len in bytes of the log.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Iterates over the values of a log.
+///
+/// Iteration stops at the end of the log, at a torn last record, or after a record with a wrong checksum.
+pub struct LogReader<R, T> {
+    reader: R,
+    buffer: Vec<u8>,
+    valid_len: u64,
+    torn: bool,
+    done: bool,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> LogReader<io::BufReader<File>, T> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(LogReader::new(io::BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read, T> LogReader<R, T> {
+    /// Read records from the start of `reader`.
+    pub fn new(reader: R) -> Self {
+        LogReader {
+            reader,
+            buffer: Vec::new(),
+            valid_len: 0,
+            torn: false,
+            done: false,
+            value: PhantomData,
+        }
+    }
+
+    /// Len of the records read so far that are complete and match their checksum.
+    pub fn valid_len(&self) -> u64 {
+        self.valid_len
+    }
+
+    /// Whether iteration stopped at an incomplete record.
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+
+    /// Read the content of the next record into the buffer, `None` at the end of the log or of its valid part.
+    fn next_record(&mut self) -> Option<Result<(), Error>> {
+        if self.done {
+            return None;
+        }
+        match self.read_record() {
+            Ok(true) => {
+                self.valid_len += (HEADER_LEN + self.buffer.len()) as u64;
+                Some(Ok(()))
+            }
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn read_record(&mut self) -> Result<bool, Error> {
+        let mut header = [0; HEADER_LEN];
+        match read_full(&mut self.reader, &mut header)? {
+            0 => return Ok(false),
+            HEADER_LEN => {}
+            _ => {
+                self.torn = true;
+                return Ok(false);
+            }
+        }
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_be_bytes(header[4..].try_into().unwrap());
+        self.buffer.clear();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut self.buffer)?;
+        if self.buffer.len() < len {
+            self.torn = true;
+            return Ok(false);
+        }
+        if crc32(&self.buffer) != crc {
+            return Err(Error::Checksum(self.valid_len));
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for LogReader<R, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.next_record()? {
+            Ok(()) => crate::from_bytes(&self.buffer).map_err(Error::Deserialize),
+            Err(err) => Err(err),
+        })
+    }
+}
+
+/// Read until `buff` is full or the end of `reader`, returning the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buff: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buff.len() {
+        match reader.read(&mut buff[read..]) {
+            Ok(0) => break,
+            Ok(len) => read += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// Truncate `file` after its last valid record, returning its new len.
+///
+/// Unless `drop_corrupt` is set, only a torn last record is removed and a corrupted record is an error.
+fn recover_file(file: &mut File, drop_corrupt: bool) -> Result<u64, Error> {
+    file.seek(io::SeekFrom::Start(0))?;
+    let mut reader = LogReader::<_, ()>::new(io::BufReader::new(&mut *file));
+    while let Some(record) = reader.next_record() {
+        match record {
+            Ok(()) => {}
+            Err(Error::Checksum(_)) if drop_corrupt => break,
+            Err(err) => return Err(err),
+        }
+    }
+    let valid_len = reader.valid_len();
+    if file.metadata()?.len() != valid_len {
+        file.set_len(valid_len)?;
+    }
+    Ok(valid_len)
+}
+
+/// Truncate the log at `path` after its last valid record, returning the number of bytes removed.
+///
+/// Records after one that doesn't match its checksum are removed too.
+pub fn recover<P: AsRef<Path>>(path: P) -> Result<u64, Error> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    Ok(len - recover_file(&mut file, true)?)
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::string::String;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Login { user: String },
+        Logout { user: String, duration: u64 },
+    }
+
+    #[test]
+    fn test_log_recovery() {
+        let path = std::env::temp_dir().join(format!("rsbin-log-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let events = [
+            Event::Login { user: "ada".into() },
+            Event::Logout {
+                user: "ada".into(),
+                duration: 3600,
+            },
+        ];
+
+        let mut log = AppendLog::open(&path).unwrap();
+        for event in &events {
+            log.append(event).unwrap();
+        }
+        let valid_len = log.len();
+        drop(log);
+
+        // a crash after writing half a record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0, 0, 0, 20, 1, 2, 3, 4, 34, 8]).unwrap();
+        drop(file);
+
+        let mut reader = LogReader::<_, Event>::open(&path).unwrap();
+        let read: Vec<Event> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(read, events);
+        assert!(reader.is_torn());
+        assert_eq!(reader.valid_len(), valid_len);
+
+        assert_eq!(recover(&path).unwrap(), 10);
+        let mut log = AppendLog::open(&path).unwrap();
+        log.append(&Event::Login { user: "bob".into() }).unwrap();
+        drop(log);
+        let mut reader = LogReader::<_, Event>::open(&path).unwrap();
+        assert_eq!(reader.by_ref().count(), 3);
+        assert!(!reader.is_torn());
+
+        // a corrupted record is kept, with the ones after it, until an explicit recover
+        let mut bytes = std::fs::read(&path).unwrap();
+        let len = bytes.len() as u64;
+        bytes[HEADER_LEN + 2] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            AppendLog::<Event>::open(&path),
+            Err(Error::Checksum(0))
+        ));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(recover(&path).unwrap(), len);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+//! Patches describing how to turn an encoded value into another, themselves serializable with rsbin.
+//!
+//! A patch is built from the `diff` of two values, sent instead of the whole new value, and applied on the old one.
+
+use alloc::{vec, vec::Vec};
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diff::{diff_values, ChangeKind, Path, PathSegment},
+    error::EndOfBuff,
+    value::{Value, VariantContent},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Operation {
+    /// Replace the value at the path.
+    Set { path: Path, value: Value },
+    /// Insert a sequence element before the index ending the path, or add a struct field or a map entry.
+    Insert { path: Path, value: Value },
+    /// Remove the sequence element, struct field or map entry at the path.
+    Remove { path: Path },
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Patch {
+    /// Operations applied in order.
+    pub operations: Vec<Operation>,
+}
+
+impl Patch {
+    /// Patch turning the encoded value `a` into the encoded value `b`.
+    pub fn diff(a: &[u8], b: &[u8]) -> crate::de::Result<Patch, EndOfBuff> {
+        let a = Value::from_bytes(a)?;
+        let b = Value::from_bytes(b)?;
+        Ok(Patch::diff_values(&a, &b))
+    }
+
+    /// Patch turning `a` into `b`.
+    pub fn diff_values(a: &Value, b: &Value) -> Patch {
+        let operations = diff_values(a, b)
+            .into_iter()
+            .map(|change| match change.kind {
+                ChangeKind::Added(value) => Operation::Insert {
+                    path: change.path,
+                    value,
+                },
+                ChangeKind::Removed(_) => Operation::Remove { path: change.path },
+                ChangeKind::Changed { to, .. } => Operation::Set {
+                    path: change.path,
+                    value: to,
+                },
+            })
+            .collect();
+        Patch { operations }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The document or a value of the patch could not be decoded.
+    Decode(crate::de::Error<EndOfBuff>),
+    /// Nothing is at this path in the document.
+    PathNotFound(Path),
+    /// The operation can't be applied to the value at this path, for example inserting in a tuple.
+    InvalidOperation(Path),
+    /// The document has type names, which values don't keep so the patched document would lose them.
+    TypeNames,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Decode(err) => Display::fmt(err, f),
+            Error::PathNotFound(path) => f.write_fmt(format_args!("Nothing found at {}.", path)),
+            Error::InvalidOperation(path) => {
+                f.write_fmt(format_args!("Invalid operation at {}.", path))
+            }
+            Error::TypeNames => f.write_str("Documents with type names can't be patched."),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<crate::de::Error<EndOfBuff>> for Error {
+    fn from(value: crate::de::Error<EndOfBuff>) -> Self {
+        Error::Decode(value)
+    }
+}
+
+/// Apply `patch` on the encoded `document`, which is rewritten once every operation succeeded.
+///
+/// The document is left untouched on error. Documents written with type names are rejected.
+pub fn apply(document: &mut Vec<u8>, patch: &Patch) -> Result<(), Error> {
+    let (mut value, type_names) = Value::parse_bytes(document)?;
+    if type_names {
+        return Err(Error::TypeNames);
+    }
+    apply_values(&mut value, patch)?;
+    document.clear();
+    value.encode(document);
+    Ok(())
+}
+
+/// Apply `patch` on `value`, operations before a failing one stay applied.
+pub fn apply_values(value: &mut Value, patch: &Patch) -> Result<(), Error> {
+    patch
+        .operations
+        .iter()
+        .try_for_each(|operation| apply_operation(value, operation))
+}
+
+fn apply_operation(root: &mut Value, operation: &Operation) -> Result<(), Error> {
+    match operation {
+        Operation::Set { path, value } => {
+            *find(root, &path.0).ok_or_else(|| Error::PathNotFound(path.clone()))? = value.clone();
+            Ok(())
+        }
+        Operation::Insert { path, value } => {
+            let (parent, last) = parent(root, path)?;
+            let invalid = || Error::InvalidOperation(path.clone());
+            match (parent, last) {
+                (Value::Seq(values), PathSegment::Index(index)) if *index <= values.len() => {
+                    values.insert(*index, value.clone())
+                }
+                (Value::Map(entries), PathSegment::Key(key)) => {
+                    if entries.iter().any(|(other, _)| other.same(key)) {
+                        return Err(invalid());
+                    }
+                    entries.push((key.clone(), value.clone()))
+                }
+                (
+                    Value::Struct(fields)
+                    | Value::Variant {
+                        content: VariantContent::Struct(fields),
+                        ..
+                    },
+                    PathSegment::Field(name),
+                ) => {
+                    if fields.iter().any(|(other, _)| other == name) {
+                        return Err(invalid());
+                    }
+                    fields.push((name.clone(), value.clone()))
+                }
+                _ => return Err(invalid()),
+            }
+            Ok(())
+        }
+        Operation::Remove { path } => {
+            let (parent, last) = parent(root, path)?;
+            let not_found = || Error::PathNotFound(path.clone());
+            match (parent, last) {
+                (Value::Seq(values), PathSegment::Index(index)) => {
+                    if *index >= values.len() {
+                        return Err(not_found());
+                    }
+                    values.remove(*index);
+                }
+                (Value::Map(entries), PathSegment::Key(key)) => {
+                    let index = entries
+                        .iter()
+                        .position(|(other, _)| other.same(key))
+                        .ok_or_else(not_found)?;
+                    entries.remove(index);
+                }
+                (
+                    Value::Struct(fields)
+                    | Value::Variant {
+                        content: VariantContent::Struct(fields),
+                        ..
+                    },
+                    PathSegment::Field(name),
+                ) => {
+                    let index = fields
+                        .iter()
+                        .position(|(other, _)| other == name)
+                        .ok_or_else(not_found)?;
+                    fields.remove(index);
+                }
+                _ => return Err(Error::InvalidOperation(path.clone())),
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The value holding the last segment of the path, and that segment.
+fn parent<'v, 'p>(
+    root: &'v mut Value,
+    path: &'p Path,
+) -> Result<(&'v mut Value, &'p PathSegment), Error> {
+    let (last, parent_path) = path
+        .0
+        .split_last()
+        .ok_or_else(|| Error::InvalidOperation(path.clone()))?;
+    let parent = find(root, parent_path).ok_or_else(|| Error::PathNotFound(path.clone()))?;
+    Ok((parent, last))
+}
+
+/// The value at `path` starting from `value`.
+pub fn find<'v>(mut value: &'v mut Value, path: &[PathSegment]) -> Option<&'v mut Value> {
+    for segment in path {
+        value = match (value, segment) {
+            (
+                Value::Struct(fields)
+                | Value::Variant {
+                    content: VariantContent::Struct(fields),
+                    ..
+                },
+                PathSegment::Field(name),
+            ) => fields
+                .iter_mut()
+                .find(|(other, _)| other == name)
+                .map(|(_, value)| value)?,
+            (
+                Value::Seq(values)
+                | Value::Tuple(values)
+                | Value::TupleStruct(values)
+                | Value::Variant {
+                    content: VariantContent::Tuple(values),
+                    ..
+                },
+                PathSegment::Index(index),
+            ) => values.get_mut(*index)?,
+            (Value::Map(entries), PathSegment::Key(key)) => entries
+                .iter_mut()
+                .find(|(other, _)| other.same(key))
+                .map(|(_, value)| value)?,
+            (Value::Option(Some(value)), PathSegment::Some) => value,
+            (
+                Value::NewTypeStruct(value)
+                | Value::Variant {
+                    content: VariantContent::NewType(value),
+                    ..
+                },
+                PathSegment::NewType,
+            ) => value,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Path(vec![])
+    }
+
+    pub fn field(mut self, name: &str) -> Self {
+        self.0.push(PathSegment::Field(name.into()));
+        self
+    }
+
+    pub fn index(mut self, index: usize) -> Self {
+        self.0.push(PathSegment::Index(index));
+        self
+    }
+
+    pub fn key(mut self, key: Value) -> Self {
+        self.0.push(PathSegment::Key(key));
+        self
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Sensor {
+        id: u32,
+        label: String,
+        readings: Vec<f32>,
+        calibration: Option<(i16, i16)>,
+    }
+
+    #[test]
+    fn test_patch() {
+        let old: Vec<Sensor> = (0..100)
+            .map(|id| Sensor {
+                id,
+                label: alloc::format!("sensor {}", id),
+                readings: vec![0.5; 8],
+                calibration: None,
+            })
+            .collect();
+        let mut new = old.clone();
+        new[42].readings.push(1.5);
+        new[42].calibration = Some((-3, 4));
+        new.remove(99);
+
+        let mut document = crate::to_bytes(&old).unwrap();
+        let new_bytes = crate::to_bytes(&new).unwrap();
+        let patch = Patch::diff(&document, &new_bytes).unwrap();
+        let patch_bytes = crate::to_bytes(&patch).unwrap();
+        assert!(patch_bytes.len() * 20 < new_bytes.len());
+
+        let patch: Patch = crate::from_bytes(&patch_bytes).unwrap();
+        apply(&mut document, &patch).unwrap();
+        let patched: Vec<Sensor> = crate::from_bytes(&document).unwrap();
+        assert_eq!(patched, new);
+
+        let missing = Patch {
+            operations: vec![Operation::Remove {
+                path: Path::new().index(500),
+            }],
+        };
+        let before = document.clone();
+        assert_eq!(
+            apply(&mut document, &missing),
+            Err(Error::PathNotFound(Path::new().index(500)))
+        );
+        assert_eq!(document, before);
+
+        let mut named = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut named).with_type_names(true);
+        old.serialize(&mut serializer).unwrap();
+        let before = named.clone();
+        assert_eq!(apply(&mut named, &patch), Err(Error::TypeNames));
+        assert_eq!(named, before);
+
+        // an unchanged document needs no operation, even with NaNs.
+        let mut unchanged = old.clone();
+        unchanged[7].readings[0] = f32::NAN;
+        let unchanged = crate::to_bytes(&unchanged).unwrap();
+        assert!(Patch::diff(&unchanged, &unchanged).unwrap().is_empty());
+    }
+}
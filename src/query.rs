@@ -0,0 +1,431 @@
+//! Extraction of a single value out of encoded data, without decoding what surrounds it.
+
+use core::fmt::{self, Display};
+use serde::Deserialize;
+
+use crate::{
+    de,
+    error::EndOfBuff,
+    tag::{Tag, UNSIZED_STRING_END_MARKER},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The path is malformed at this byte offset.
+    InvalidPath(usize),
+    /// Nothing matches the segment of the path starting at this byte offset.
+    NotFound(usize),
+    /// The data is malformed before reaching the value.
+    InvalidData,
+    /// The value could not be deserialized.
+    Decode(de::Error<EndOfBuff>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPath(position) => {
+                f.write_fmt(format_args!("Invalid path at offset {}.", position))
+            }
+            Error::NotFound(position) => f.write_fmt(format_args!(
+                "Nothing found for the path segment at offset {}.",
+                position
+            )),
+            Error::InvalidData => f.write_str("Invalid data before the queried value."),
+            Error::Decode(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<de::Error<EndOfBuff>> for Error {
+    fn from(value: de::Error<EndOfBuff>) -> Self {
+        Error::Decode(value)
+    }
+}
+
+/// Deserialize the value at `path` in the encoded `bytes`, borrowing from them when possible.
+///
+/// A path is a sequence of segments, an empty path being the whole value:
+/// - `.name` is a struct field, a field of a struct variant or the value of a string key of a map,
+/// - `["name"]` is the value of a string key of a map, or a field,
+/// - `[3]` is an element of a sequence, a tuple or a tuple variant, or the value of an integer key of a map.
+///
+/// Options, newtype structs and newtype variants are stepped through. Everything before the value is
+/// skipped over without being decoded nor allocating, and what comes after it is not read. As the type
+/// names skipped over are not read, a value referring to one of them by its index fails to decode.
+pub fn query<'de, T>(bytes: &'de [u8], path: &str) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    let mut walker = Walker { bytes };
+    let mut segments = Segments { path, position: 0 };
+    while let Some((position, segment)) = segments.next_segment()? {
+        match walker.step(&segment) {
+            Some(true) => {}
+            Some(false) => return Err(Error::NotFound(position)),
+            None => return Err(Error::InvalidData),
+        }
+    }
+    Ok(crate::from_bytes(walker.bytes)?)
+}
+
+enum Segment<'p> {
+    /// `.name` or `["name"]`
+    Name(&'p str),
+    /// `[3]`
+    Index(u64),
+}
+
+struct Segments<'p> {
+    path: &'p str,
+    position: usize,
+}
+
+impl<'p> Segments<'p> {
+    /// The next segment and the offset it starts at.
+    fn next_segment(&mut self) -> Result<Option<(usize, Segment<'p>)>, Error> {
+        let start = self.position;
+        let rest = &self.path[start..];
+        let invalid = Error::InvalidPath(start);
+        let (segment, len) = if let Some(field) = rest.strip_prefix('.') {
+            let name = field.split(['.', '[']).next().unwrap_or_default();
+            if name.is_empty() {
+                return Err(invalid);
+            }
+            (Segment::Name(name), name.len() + 1)
+        } else if let Some(key) = rest.strip_prefix("[\"") {
+            let name = key
+                .split("\"]")
+                .next()
+                .filter(|name| name.len() < key.len());
+            let name = name.ok_or(invalid)?;
+            (Segment::Name(name), name.len() + 4)
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let digits = index
+                .split(']')
+                .next()
+                .filter(|digits| digits.len() < index.len());
+            let index = digits.and_then(|digits| digits.parse().ok());
+            let index = index.ok_or(Error::InvalidPath(start))?;
+            (Segment::Index(index), digits.unwrap_or_default().len() + 2)
+        } else if rest.is_empty() {
+            return Ok(None);
+        } else {
+            return Err(invalid);
+        };
+        self.position += len;
+        Ok(Some((start, segment)))
+    }
+}
+
+/// Walk the tag stream without validating what is skipped, every function returns `None` when the data is malformed.
+struct Walker<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Walker<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn peek(&self) -> Option<Tag> {
+        Tag::try_from(*self.bytes.first()?).ok()
+    }
+
+    fn tag(&mut self) -> Option<Tag> {
+        let tag = self.peek()?;
+        self.bytes = &self.bytes[1..];
+        Some(tag)
+    }
+
+    fn number(&mut self, len: usize) -> Option<u128> {
+        let bytes = self.take(len)?;
+        Some(
+            bytes
+                .iter()
+                .fold(0u128, |acc, byte| (acc << 8) | u128::from(*byte)),
+        )
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let len = match self.tag()? {
+            Tag::U8 => self.number(1)?,
+            Tag::U16 => self.number(2)?,
+            Tag::U32 => self.number(4)?,
+            Tag::U64 => self.number(8)?,
+            _ => return None,
+        };
+        usize::try_from(len).ok()
+    }
+
+    fn until_end_marker(&mut self) -> Option<&'a [u8]> {
+        let len = memchr::memmem::find(self.bytes, &UNSIZED_STRING_END_MARKER)?;
+        let str = self.take(len)?;
+        self.take(2)?;
+        Some(str)
+    }
+
+    /// Whether an unsized collection has more elements, popping its end tag when it has not.
+    fn has_next(&mut self) -> Option<bool> {
+        if self.peek()? == Tag::UnsizedSeqEnd {
+            self.tag()?;
+            Some(false)
+        } else {
+            Some(true)
+        }
+    }
+
+    fn skip_n(&mut self, count: usize) -> Option<()> {
+        (0..count).try_for_each(|_| self.skip())
+    }
+
+    fn skip(&mut self) -> Option<()> {
+        match self.tag()? {
+            Tag::None | Tag::BoolFalse | Tag::BoolTrue | Tag::Unit | Tag::UnitStruct => Some(()),
+            Tag::Some | Tag::NewTypeStruct | Tag::UnitVariant => self.skip(),
+            Tag::I8 | Tag::U8 | Tag::Char1 => self.take(1).map(drop),
+            Tag::I16 | Tag::U16 | Tag::Char2 => self.take(2).map(drop),
+            Tag::Char3 => self.take(3).map(drop),
+            Tag::I32 | Tag::U32 | Tag::F32 | Tag::Char4 => self.take(4).map(drop),
+            Tag::I64 | Tag::U64 | Tag::F64 => self.take(8).map(drop),
+            #[cfg(not(no_integer128))]
+            Tag::I128 | Tag::U128 => self.take(16).map(drop),
+            Tag::String | Tag::Bytes => {
+                let len = self.len()?;
+                self.take(len).map(drop)
+            }
+            Tag::MarkerTerminatedString => self.until_end_marker().map(drop),
+            Tag::ChunkedString | Tag::ChunkedBytes => {
+                while self.has_next()? {
+                    let len = self.len()?;
+                    self.take(len)?;
+                }
+                Some(())
+            }
+            Tag::NewTypeVariant => self.skip_n(2),
+            Tag::TupleVariant => {
+                self.skip()?;
+                let len = self.len()?;
+                self.skip_n(len)
+            }
+            Tag::StructVariant => {
+                self.skip()?;
+                let len = self.len()?;
+                self.skip_n(len.checked_mul(2)?)
+            }
+            Tag::Seq | Tag::Tuple | Tag::TupleStruct => {
+                let len = self.len()?;
+                self.skip_n(len)
+            }
+            Tag::Map | Tag::Struct => {
+                let len = self.len()?;
+                self.skip_n(len.checked_mul(2)?)
+            }
+            Tag::UnsizedSeq => {
+                while self.has_next()? {
+                    self.skip()?;
+                }
+                Some(())
+            }
+            Tag::UnsizedMap => {
+                while self.has_next()? {
+                    self.skip_n(2)?;
+                }
+                Some(())
+            }
+            Tag::TypeName => self.skip_n(2),
+            Tag::UnsizedSeqEnd => None,
+        }
+    }
+
+    /// Pop a map key or a field name, and whether it matches `segment`.
+    fn key_matches(&mut self, segment: &Segment) -> Option<bool> {
+        let start = self.bytes;
+        let matches = match (self.tag()?, segment) {
+            (Tag::String, Segment::Name(name)) => {
+                let len = self.len()?;
+                self.take(len)? == name.as_bytes()
+            }
+            (Tag::MarkerTerminatedString, Segment::Name(name)) => {
+                self.until_end_marker()? == name.as_bytes()
+            }
+            (
+                tag @ (Tag::U8
+                | Tag::U16
+                | Tag::U32
+                | Tag::U64
+                | Tag::I8
+                | Tag::I16
+                | Tag::I32
+                | Tag::I64),
+                Segment::Index(index),
+            ) => {
+                let (len, signed) = match tag {
+                    Tag::U8 => (1, false),
+                    Tag::U16 => (2, false),
+                    Tag::U32 => (4, false),
+                    Tag::U64 => (8, false),
+                    Tag::I8 => (1, true),
+                    Tag::I16 => (2, true),
+                    Tag::I32 => (4, true),
+                    _ => (8, true),
+                };
+                let value = self.number(len)?;
+                // a negative key has its sign bit set and can't match.
+                !(signed && value >> (len * 8 - 1) != 0) && value == u128::from(*index)
+            }
+            _ => {
+                self.bytes = start;
+                self.skip()?;
+                false
+            }
+        };
+        Some(matches)
+    }
+
+    /// Look for the value of the entry whose key matches `segment` in `count` entries, or until the end tag.
+    fn entries(&mut self, count: Option<usize>, segment: &Segment) -> Option<bool> {
+        let mut remaining = count;
+        loop {
+            match &mut remaining {
+                Some(0) => return Some(false),
+                Some(remaining) => *remaining -= 1,
+                None if !self.has_next()? => return Some(false),
+                None => {}
+            }
+            if self.key_matches(segment)? {
+                return Some(true);
+            }
+            self.skip()?;
+        }
+    }
+
+    fn elements(&mut self, count: Option<usize>, index: u64) -> Option<bool> {
+        let Ok(index) = usize::try_from(index) else {
+            return Some(false);
+        };
+        match count {
+            Some(count) if index >= count => Some(false),
+            Some(_) => self.skip_n(index).map(|_| true),
+            None => {
+                for _ in 0..index {
+                    if !self.has_next()? {
+                        return Some(false);
+                    }
+                    self.skip()?;
+                }
+                Some(self.peek()? != Tag::UnsizedSeqEnd)
+            }
+        }
+    }
+
+    /// Move to the part of the current value designated by `segment`, if it has one.
+    fn step(&mut self, segment: &Segment) -> Option<bool> {
+        loop {
+            match (self.tag()?, segment) {
+                (Tag::Some | Tag::NewTypeStruct, _) => {}
+                (Tag::TypeName, _) => self.skip()?,
+                (Tag::NewTypeVariant, _) => self.skip()?,
+                (Tag::Struct | Tag::Map, _) => {
+                    let len = self.len()?;
+                    return self.entries(Some(len), segment);
+                }
+                (Tag::StructVariant, Segment::Name(_)) => {
+                    self.skip()?;
+                    let len = self.len()?;
+                    return self.entries(Some(len), segment);
+                }
+                (Tag::UnsizedMap, _) => return self.entries(None, segment),
+                (Tag::Seq | Tag::Tuple | Tag::TupleStruct, Segment::Index(index)) => {
+                    let len = self.len()?;
+                    return self.elements(Some(len), *index);
+                }
+                (Tag::TupleVariant, Segment::Index(index)) => {
+                    self.skip()?;
+                    let len = self.len()?;
+                    return self.elements(Some(len), *index);
+                }
+                (Tag::UnsizedSeq, Segment::Index(index)) => return self.elements(None, *index),
+                _ => return Some(false),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Header {
+        request_id: u64,
+        host: String,
+    }
+
+    #[derive(Serialize)]
+    enum Body {
+        Upload {
+            name: String,
+            parts: Vec<(u32, Vec<u8>)>,
+        },
+    }
+
+    #[derive(Serialize)]
+    struct Request {
+        payload: Vec<u8>,
+        header: Option<Header>,
+        limits: BTreeMap<String, u32>,
+        ports: BTreeMap<u16, String>,
+        body: Body,
+    }
+
+    #[test]
+    fn test_query() {
+        let request = Request {
+            payload: vec![7; 1000],
+            header: Some(Header {
+                request_id: 42,
+                host: "example.org".into(),
+            }),
+            limits: BTreeMap::from([("conn".into(), 100), ("rate".into(), 10)]),
+            ports: BTreeMap::from([(80, "http".into()), (443, "https".into())]),
+            body: Body::Upload {
+                name: "file".into(),
+                parts: vec![(0, vec![1, 2]), (1, vec![3])],
+            },
+        };
+        let bytes = crate::to_bytes(&request).unwrap();
+
+        assert_eq!(query::<u64>(&bytes, ".header.request_id"), Ok(42));
+        let host: &str = query(&bytes, ".header.host").unwrap();
+        assert_eq!(host, "example.org");
+        assert_eq!(query::<u32>(&bytes, ".limits[\"rate\"]"), Ok(10));
+        assert_eq!(query::<&str>(&bytes, ".ports[443]"), Ok("https"));
+        assert_eq!(query::<Vec<u8>>(&bytes, ".body.parts[1][1]"), Ok(vec![3]));
+        assert_eq!(query::<u8>(&bytes, ".payload[999]"), Ok(7));
+
+        assert_eq!(
+            query::<u8>(&bytes, ".payload[1000]"),
+            Err(Error::NotFound(8))
+        );
+        assert_eq!(
+            query::<u64>(&bytes, ".header.missing"),
+            Err(Error::NotFound(7))
+        );
+        assert_eq!(query::<u64>(&bytes, "header"), Err(Error::InvalidPath(0)));
+        assert_eq!(
+            query::<u64>(&bytes, ".ports[80"),
+            Err(Error::InvalidPath(6))
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Serialization of trait objects, by registering the concrete types that can be behind them.
+//!
+//! A trait object is serialized as a newtype variant of the enum `REGISTERED`, whose variant is the registered
+//! type and whose content is the concrete value. So `VariantEncoding` picks whether types are identified by their
+//! id, their name or both.
+//!
+//! The serialized trait has `Polymorphic` as a supertrait and implements `Serialize` with `serialize`, each
+//! concrete type implements `Registered` and is registered in the `Registry` used to deserialize the trait objects.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, EnumAccess, Unexpected, VariantAccess},
+    Deserializer, Serialize, Serializer,
+};
+
+use crate::error::EndOfBuff;
+
+/// Name of the enum trait objects are serialized as a variant of.
+pub const REGISTERED: &str = "$rsbin::Registered";
+
+/// Concrete type that can be behind a trait object, with the id and the name identifying it.
+///
+/// Ids and names must stay the same for encoded data to stay readable.
+pub trait Registered: Serialize {
+    const ID: u32;
+    const NAME: &'static str;
+}
+
+/// Object safe side of `Registered`, to be a supertrait of serialized traits.
+pub trait Polymorphic: erased_serde::Serialize {
+    fn registered_id(&self) -> u32;
+
+    fn registered_name(&self) -> &'static str;
+}
+
+impl<T: Registered> Polymorphic for T {
+    fn registered_id(&self) -> u32 {
+        T::ID
+    }
+
+    fn registered_name(&self) -> &'static str {
+        T::NAME
+    }
+}
+
+/// Serialize `value` with its registered id, meant to implement `Serialize` for trait objects.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: ?Sized + Polymorphic,
+    S: Serializer,
+{
+    struct Erased<'a, T: ?Sized>(&'a T);
+
+    impl<T: ?Sized + Polymorphic> Serialize for Erased<'_, T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            erased_serde::serialize(self.0, serializer)
+        }
+    }
+
+    serializer.serialize_newtype_variant(
+        REGISTERED,
+        value.registered_id(),
+        value.registered_name(),
+        &Erased(value),
+    )
+}
+
+type DeserializeFn<T> =
+    Box<dyn Fn(&mut dyn erased_serde::Deserializer) -> Result<Box<T>, erased_serde::Error>>;
+
+struct Entry<T: ?Sized> {
+    id: u32,
+    name: &'static str,
+    deserialize: DeserializeFn<T>,
+}
+
+/// Concrete types that can be deserialized as `Box<T>`, `T` being a trait object.
+///
+/// `&Registry<T>` is a `DeserializeSeed` of `Box<T>`.
+pub struct Registry<T: ?Sized> {
+    entries: Vec<Entry<T>>,
+}
+
+impl<T: ?Sized> Default for Registry<T> {
+    fn default() -> Self {
+        Registry {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T: ?Sized + 'static> Registry<T> {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Register `C`, `into` boxing it as a `T`.
+    ///
+    /// Panics if its id or its name is already registered.
+    pub fn register<C>(&mut self, into: fn(C) -> Box<T>) -> &mut Self
+    where
+        C: Registered + DeserializeOwned + 'static,
+    {
+        assert!(
+            self.entries
+                .iter()
+                .all(|entry| entry.id != C::ID && entry.name != C::NAME),
+            "{} or {} is already registered",
+            C::ID,
+            C::NAME
+        );
+        self.entries.push(Entry {
+            id: C::ID,
+            name: C::NAME,
+            deserialize: Box::new(move |deserializer| {
+                erased_serde::deserialize::<C>(deserializer).map(into)
+            }),
+        });
+        self
+    }
+
+    pub fn from_bytes(&self, bytes: &[u8]) -> crate::de::Result<Box<T>, EndOfBuff> {
+        let mut deserializer = crate::Deserializer::new(bytes);
+        self.deserialize(&mut deserializer)
+    }
+}
+
+impl<'de, T: ?Sized> DeserializeSeed<'de> for &Registry<T> {
+    type Value = Box<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum(REGISTERED, &[], RegistryVisitor(self))
+    }
+}
+
+struct RegistryVisitor<'r, T: ?Sized>(&'r Registry<T>);
+
+impl<'de, 'r, T: ?Sized> de::Visitor<'de> for RegistryVisitor<'r, T> {
+    type Value = Box<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a registered type")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (entry, variant) = data.variant_seed(EntryIdentifier(self.0))?;
+        variant.newtype_variant_seed(EntryContent(entry))
+    }
+}
+
+/// Find the entry of a type from its id or name.
+struct EntryIdentifier<'r, T: ?Sized>(&'r Registry<T>);
+
+impl<'de, 'r, T: ?Sized> DeserializeSeed<'de> for EntryIdentifier<'r, T> {
+    type Value = &'r Entry<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de, 'r, T: ?Sized> de::Visitor<'de> for EntryIdentifier<'r, T> {
+    type Value = &'r Entry<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the id or the name of a registered type")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        self.0
+            .entries
+            .iter()
+            .find(|entry| u64::from(entry.id) == v)
+            .ok_or_else(|| E::invalid_value(Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        self.0
+            .entries
+            .iter()
+            .find(|entry| entry.name == v)
+            .ok_or_else(|| E::invalid_value(Unexpected::Str(v), &self))
+    }
+}
+
+/// Deserialize the content of a registered type.
+struct EntryContent<'r, T: ?Sized>(&'r Entry<T>);
+
+impl<'de, T: ?Sized> DeserializeSeed<'de> for EntryContent<'_, T> {
+    type Value = Box<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.0.deserialize)(&mut erased).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use crate::{Serializer as RsbinSerializer, VariantEncoding};
+    use alloc::string::String;
+    use serde::Deserialize;
+
+    trait Event: Polymorphic {
+        fn user(&self) -> &str;
+    }
+
+    impl Serialize for dyn Event {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(self, serializer)
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Login {
+        user: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Logout {
+        user: String,
+        duration: u64,
+    }
+
+    impl Registered for Login {
+        const ID: u32 = 1;
+        const NAME: &'static str = "Login";
+    }
+
+    impl Registered for Logout {
+        const ID: u32 = 2;
+        const NAME: &'static str = "Logout";
+    }
+
+    impl Event for Login {
+        fn user(&self) -> &str {
+            &self.user
+        }
+    }
+
+    impl Event for Logout {
+        fn user(&self) -> &str {
+            &self.user
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::<dyn Event>::new();
+        registry
+            .register::<Login>(|login| Box::new(login))
+            .register::<Logout>(|logout| Box::new(logout));
+
+        let event: Box<dyn Event> = Box::new(Logout {
+            user: "ada".into(),
+            duration: 60,
+        });
+        let bytes = crate::to_bytes(&event).unwrap();
+        let read = registry.from_bytes(&bytes).unwrap();
+        assert_eq!(read.registered_name(), "Logout");
+        assert_eq!(read.user(), "ada");
+
+        let mut by_name = Vec::new();
+        let mut serializer =
+            RsbinSerializer::new(&mut by_name).with_variant_encoding(VariantEncoding::Name);
+        event.serialize(&mut serializer).unwrap();
+        assert!(by_name.windows(6).any(|window| window == b"Logout"));
+        assert_eq!(registry.from_bytes(&by_name).unwrap().registered_id(), 2);
+
+        let mut login_only = Registry::<dyn Event>::new();
+        login_only.register::<Login>(|login| Box::new(login));
+        assert!(login_only.from_bytes(&bytes).is_err());
+    }
+}
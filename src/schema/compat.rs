@@ -0,0 +1,397 @@
+use alloc::{
+    borrow::ToOwned,
+    collections::BTreeSet,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Display};
+use serde::Deserialize;
+
+use super::{trace, ContainerFormat, Error, Format, Named, Schema, VariantFormat};
+
+/// Reason why data written with one schema can't be read with another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IncompatibilityKind {
+    /// The reader expects a struct field that is not written, fine only if the field has a default.
+    MissingField(String),
+    /// The written variant does not exist in the reader.
+    UnknownVariant { index: u32, name: String },
+    /// The variant moved, variants are identified by index so it is read as another one.
+    VariantIndexChanged {
+        name: String,
+        written: u32,
+        read: u32,
+    },
+    /// The variant at this index has another name in the reader.
+    VariantRenamed {
+        index: u32,
+        written: String,
+        read: String,
+    },
+    /// The number is written with a type that the reader can't widen.
+    NarrowedNumber { written: Format, read: Format },
+    /// The kind of value written is not the one expected by the reader.
+    KindChanged { written: String, read: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Incompatibility {
+    /// Where in the value the problem is, fields are prefixed by `.`, variants by `::`,
+    /// sequence elements are `[]`, map keys and values `{key}` and `{value}` and optional values `?`.
+    pub path: String,
+    pub kind: IncompatibilityKind,
+}
+
+impl Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "<root>"
+        } else {
+            &self.path
+        };
+        match &self.kind {
+            IncompatibilityKind::MissingField(name) => f.write_fmt(format_args!(
+                "{}: field {} is not written, it needs a default",
+                path, name
+            )),
+            IncompatibilityKind::UnknownVariant { index, name } => f.write_fmt(format_args!(
+                "{}: variant {} (index {}) is unknown to the reader",
+                path, name, index
+            )),
+            IncompatibilityKind::VariantIndexChanged {
+                name,
+                written,
+                read,
+            } => f.write_fmt(format_args!(
+                "{}: variant {} moved from index {} to {}",
+                path, name, written, read
+            )),
+            IncompatibilityKind::VariantRenamed {
+                index,
+                written,
+                read,
+            } => f.write_fmt(format_args!(
+                "{}: variant {} at index {} is read as {}",
+                path, written, index, read
+            )),
+            IncompatibilityKind::NarrowedNumber { written, read } => f.write_fmt(format_args!(
+                "{}: {:?} can't be read as {:?}",
+                path, written, read
+            )),
+            IncompatibilityKind::KindChanged { written, read } => f.write_fmt(format_args!(
+                "{}: {} can't be read as {}",
+                path, written, read
+            )),
+        }
+    }
+}
+
+/// Compatibility between an old and a new version of a schema.
+///
+/// Enum variants are assumed to be encoded by index, the default `VariantEncoding`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Compatibility {
+    /// Problems when reading data written with the old schema using the new one.
+    pub backward: Vec<Incompatibility>,
+    /// Problems when reading data written with the new schema using the old one.
+    pub forward: Vec<Incompatibility>,
+}
+
+impl Compatibility {
+    pub fn is_backward_compatible(&self) -> bool {
+        self.backward.is_empty()
+    }
+
+    pub fn is_forward_compatible(&self) -> bool {
+        self.forward.is_empty()
+    }
+
+    pub fn is_fully_compatible(&self) -> bool {
+        self.is_backward_compatible() && self.is_forward_compatible()
+    }
+}
+
+/// Check both ways whether data written with one schema can be read with the other.
+pub fn check_compatibility(old: &Schema, new: &Schema) -> Compatibility {
+    Compatibility {
+        backward: check_readable(old, new),
+        forward: check_readable(new, old),
+    }
+}
+
+/// Every problem encountered when reading data written with the `writer` schema using the `reader` one.
+pub fn check_readable(writer: &Schema, reader: &Schema) -> Vec<Incompatibility> {
+    let mut checker = Checker {
+        writer,
+        reader,
+        visited: BTreeSet::new(),
+        path: String::new(),
+        problems: Vec::new(),
+    };
+    checker.check(&writer.root, &reader.root);
+    checker.problems
+}
+
+/// Trace both versions of a type and check their compatibility.
+pub fn check_types<'a, 'b, Old, New>() -> Result<Compatibility, Error>
+where
+    Old: Deserialize<'a>,
+    New: Deserialize<'b>,
+{
+    let old = trace::<Old>()?;
+    let new = trace::<New>()?;
+    Ok(check_compatibility(&old, &new))
+}
+
+/// Test helper panicking with every problem when data written with `Old` can't be read as `New`.
+pub fn assert_backward_compatible<'a, 'b, Old, New>()
+where
+    Old: Deserialize<'a>,
+    New: Deserialize<'b>,
+{
+    let compatibility = check_types::<Old, New>().unwrap_or_else(|err| panic!("{}", err));
+    assert_no_problems("backward", &compatibility.backward);
+}
+
+/// Test helper panicking with every problem when data written with `Old` can't be read as `New` and vice versa.
+pub fn assert_fully_compatible<'a, 'b, Old, New>()
+where
+    Old: Deserialize<'a>,
+    New: Deserialize<'b>,
+{
+    let compatibility = check_types::<Old, New>().unwrap_or_else(|err| panic!("{}", err));
+    assert_no_problems("backward", &compatibility.backward);
+    assert_no_problems("forward", &compatibility.forward);
+}
+
+fn assert_no_problems(direction: &str, problems: &[Incompatibility]) {
+    if !problems.is_empty() {
+        let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+        panic!(
+            "Types are not {} compatible:\n{}",
+            direction,
+            problems.join("\n")
+        );
+    }
+}
+
+struct Checker<'s> {
+    writer: &'s Schema,
+    reader: &'s Schema,
+    /// Pairs of containers already checked, to stop on recursive types.
+    visited: BTreeSet<(&'s str, &'s str)>,
+    path: String,
+    problems: Vec<Incompatibility>,
+}
+
+/// Rank of a number in its family, numbers can be read by a type of the same family with a higher or equal rank.
+fn number_rank(format: &Format) -> Option<(u8, u8)> {
+    match format {
+        Format::I8 => Some((0, 0)),
+        Format::I16 => Some((0, 1)),
+        Format::I32 => Some((0, 2)),
+        Format::I64 => Some((0, 3)),
+        Format::I128 => Some((0, 4)),
+        Format::U8 => Some((1, 0)),
+        Format::U16 => Some((1, 1)),
+        Format::U32 => Some((1, 2)),
+        Format::U64 => Some((1, 3)),
+        Format::U128 => Some((1, 4)),
+        Format::F32 => Some((2, 0)),
+        Format::F64 => Some((2, 1)),
+        _ => None,
+    }
+}
+
+fn kind_name(format: &Format) -> String {
+    match format {
+        Format::TypeName(name) => name.clone(),
+        Format::Option(_) => "Option".to_owned(),
+        Format::Seq(_) => "Seq".to_owned(),
+        Format::Map { .. } => "Map".to_owned(),
+        Format::Tuple(elements) => format!("Tuple of {} elements", elements.len()),
+        format => format!("{:?}", format),
+    }
+}
+
+fn container_kind_name(format: &ContainerFormat) -> &'static str {
+    match format {
+        ContainerFormat::UnitStruct => "unit struct",
+        ContainerFormat::NewTypeStruct(_) => "newtype struct",
+        ContainerFormat::TupleStruct(_) => "tuple struct",
+        ContainerFormat::Struct(_) => "struct",
+        ContainerFormat::Enum(_) => "enum",
+    }
+}
+
+fn variant_kind_name(format: &VariantFormat) -> &'static str {
+    match format {
+        VariantFormat::Unit => "unit variant",
+        VariantFormat::NewType(_) => "newtype variant",
+        VariantFormat::Tuple(_) => "tuple variant",
+        VariantFormat::Struct(_) => "struct variant",
+    }
+}
+
+impl<'s> Checker<'s> {
+    fn report(&mut self, kind: IncompatibilityKind) {
+        self.problems.push(Incompatibility {
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    fn kind_changed(&mut self, written: String, read: String) {
+        self.report(IncompatibilityKind::KindChanged { written, read });
+    }
+
+    fn with_segment<F: FnOnce(&mut Self)>(&mut self, segment: &str, f: F) {
+        let len = self.path.len();
+        self.path.push_str(segment);
+        f(self);
+        self.path.truncate(len);
+    }
+
+    fn check(&mut self, written: &'s Format, read: &'s Format) {
+        match (written, read) {
+            (Format::Unknown, _) | (_, Format::Unknown) => {}
+            (Format::TypeName(written), Format::TypeName(read)) => {
+                self.check_containers(written, read)
+            }
+            (Format::Option(written), Format::Option(read)) => {
+                self.with_segment("?", |this| this.check(written, read))
+            }
+            (Format::Seq(written), Format::Seq(read)) => {
+                self.with_segment("[]", |this| this.check(written, read))
+            }
+            (
+                Format::Map { key, value },
+                Format::Map {
+                    key: read_key,
+                    value: read_value,
+                },
+            ) => {
+                self.with_segment("{key}", |this| this.check(key, read_key));
+                self.with_segment("{value}", |this| this.check(value, read_value));
+            }
+            (Format::Tuple(written), Format::Tuple(read)) => self.check_elements(written, read),
+            (written, read) => match (number_rank(written), number_rank(read)) {
+                (Some((family, rank)), Some((read_family, read_rank))) if family == read_family => {
+                    if rank > read_rank {
+                        self.report(IncompatibilityKind::NarrowedNumber {
+                            written: written.clone(),
+                            read: read.clone(),
+                        });
+                    }
+                }
+                _ if written == read => {}
+                _ => self.kind_changed(kind_name(written), kind_name(read)),
+            },
+        }
+    }
+
+    fn check_elements(&mut self, written: &'s [Format], read: &'s [Format]) {
+        if written.len() != read.len() {
+            self.kind_changed(
+                format!("{} elements", written.len()),
+                format!("{} elements", read.len()),
+            );
+            return;
+        }
+        for (index, (written, read)) in written.iter().zip(read).enumerate() {
+            self.with_segment(&format!(".{}", index), |this| this.check(written, read));
+        }
+    }
+
+    fn check_fields(&mut self, written: &'s [Named<Format>], read: &'s [Named<Format>]) {
+        // fields are encoded by name, so they can be reordered, and unknown ones are ignored.
+        for read_field in read {
+            match written.iter().find(|field| field.name == read_field.name) {
+                Some(field) => self.with_segment(&format!(".{}", field.name), |this| {
+                    this.check(&field.value, &read_field.value)
+                }),
+                None => self.report(IncompatibilityKind::MissingField(read_field.name.clone())),
+            }
+        }
+    }
+
+    fn check_containers(&mut self, written: &'s str, read: &'s str) {
+        if !self.visited.insert((written, read)) {
+            return;
+        }
+        let (Some(written_format), Some(read_format)) = (
+            self.writer.containers.get(written),
+            self.reader.containers.get(read),
+        ) else {
+            return;
+        };
+        match (written_format, read_format) {
+            (ContainerFormat::UnitStruct, ContainerFormat::UnitStruct) => {}
+            (ContainerFormat::NewTypeStruct(written), ContainerFormat::NewTypeStruct(read)) => {
+                self.check(written, read)
+            }
+            (ContainerFormat::TupleStruct(written), ContainerFormat::TupleStruct(read)) => {
+                self.check_elements(written, read)
+            }
+            (ContainerFormat::Struct(written), ContainerFormat::Struct(read)) => {
+                self.check_fields(written, read)
+            }
+            (ContainerFormat::Enum(written), ContainerFormat::Enum(read)) => {
+                for (index, variant) in written {
+                    match read.get(index) {
+                        Some(read_variant) if read_variant.name == variant.name => self
+                            .with_segment(&format!("::{}", variant.name), |this| {
+                                this.check_variants(&variant.value, &read_variant.value)
+                            }),
+                        read_variant => {
+                            let moved = read.iter().find(|(_, read)| read.name == variant.name);
+                            let kind = match (moved, read_variant) {
+                                (Some((read_index, _)), _) => {
+                                    IncompatibilityKind::VariantIndexChanged {
+                                        name: variant.name.clone(),
+                                        written: *index,
+                                        read: *read_index,
+                                    }
+                                }
+                                (None, Some(read_variant)) => IncompatibilityKind::VariantRenamed {
+                                    index: *index,
+                                    written: variant.name.clone(),
+                                    read: read_variant.name.clone(),
+                                },
+                                (None, None) => IncompatibilityKind::UnknownVariant {
+                                    index: *index,
+                                    name: variant.name.clone(),
+                                },
+                            };
+                            self.report(kind);
+                        }
+                    }
+                }
+            }
+            (written, read) => self.kind_changed(
+                container_kind_name(written).to_owned(),
+                container_kind_name(read).to_owned(),
+            ),
+        }
+    }
+
+    fn check_variants(&mut self, written: &'s VariantFormat, read: &'s VariantFormat) {
+        match (written, read) {
+            (VariantFormat::Unit, VariantFormat::Unit) => {}
+            (VariantFormat::NewType(written), VariantFormat::NewType(read)) => {
+                self.check(written, read)
+            }
+            (VariantFormat::Tuple(written), VariantFormat::Tuple(read)) => {
+                self.check_elements(written, read)
+            }
+            (VariantFormat::Struct(written), VariantFormat::Struct(read)) => {
+                self.check_fields(written, read)
+            }
+            (written, read) => self.kind_changed(
+                variant_kind_name(written).to_owned(),
+                variant_kind_name(read).to_owned(),
+            ),
+        }
+    }
+}
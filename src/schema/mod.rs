@@ -0,0 +1,467 @@
+//! Description of the rsbin wire format of Rust types.
+//!
+//! A `Schema` is obtained by tracing a type with `trace`, and is itself serializable
+//! so it can be stored next to the data it describes, and used to `validate` data without the traced type.
+
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Display};
+use serde::{Deserialize, Serialize};
+
+mod compat;
+mod trace;
+mod validate;
+
+pub use compat::{
+    assert_backward_compatible, assert_fully_compatible, check_compatibility, check_readable,
+    check_types, Compatibility, Incompatibility, IncompatibilityKind,
+};
+pub use trace::trace;
+pub use validate::{validate, ValidationError};
+
+/// Format of a value as it appears on the wire.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Format {
+    /// The format could not be determined, for example the element of a sequence only reached through recursion.
+    Unknown,
+    /// Reference to a named container described in `Schema::containers`.
+    TypeName(String),
+    Unit,
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    F32,
+    F64,
+    Char,
+    Str,
+    Bytes,
+    Option(Box<Format>),
+    Seq(Box<Format>),
+    Map {
+        key: Box<Format>,
+        value: Box<Format>,
+    },
+    Tuple(Vec<Format>),
+}
+
+/// A field of a struct or a variant of an enum.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Named<T> {
+    pub name: String,
+    pub value: T,
+}
+
+/// Format of a named type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContainerFormat {
+    UnitStruct,
+    NewTypeStruct(Box<Format>),
+    TupleStruct(Vec<Format>),
+    Struct(Vec<Named<Format>>),
+    /// Variants by index.
+    Enum(BTreeMap<u32, Named<VariantFormat>>),
+}
+
+/// Format of the content of an enum variant.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VariantFormat {
+    Unit,
+    NewType(Box<Format>),
+    Tuple(Vec<Format>),
+    Struct(Vec<Named<Format>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Schema {
+    /// Format of the traced type.
+    pub root: Format,
+    /// Named types reachable from the root, by name.
+    pub containers: BTreeMap<String, ContainerFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The type needs to know what is on the wire, which is not supported while tracing.
+    DeserializeAny,
+    /// Two occurences of the same type were traced with different formats.
+    Incompatible(String),
+    /// Some variants of the enum could not be reached.
+    Incomplete(String),
+    /// The type recurses without end, the first variant of a recursive enum must not recurse.
+    Recursion(String),
+    Custom(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeserializeAny => {
+                f.write_str("Types relying on deserialize_any can't be traced.")
+            }
+            Error::Incompatible(name) => {
+                f.write_fmt(format_args!("Incompatible formats traced for {}.", name))
+            }
+            Error::Incomplete(name) => f.write_fmt(format_args!(
+                "Could not reach every variant of the enum {}.",
+                name
+            )),
+            Error::Recursion(name) => f.write_fmt(format_args!(
+                "Endless recursion while tracing {}, the first variant of a recursive enum must not recurse.",
+                name
+            )),
+            Error::Custom(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl serde::de::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: Display,
+    {
+        Error::Custom(msg.to_string())
+    }
+}
+
+impl Format {
+    /// Merge the information of `other` in `self`, `Format::Unknown` is compatible with every format.
+    fn unify(&mut self, other: Format) -> Result<(), Error> {
+        match (self, other) {
+            (_, Format::Unknown) => Ok(()),
+            (this @ Format::Unknown, other) => {
+                *this = other;
+                Ok(())
+            }
+            (Format::Option(this), Format::Option(other))
+            | (Format::Seq(this), Format::Seq(other)) => this.unify(*other),
+            (
+                Format::Map { key, value },
+                Format::Map {
+                    key: other_key,
+                    value: other_value,
+                },
+            ) => {
+                key.unify(*other_key)?;
+                value.unify(*other_value)
+            }
+            (Format::Tuple(this), Format::Tuple(other)) => unify_formats(this, other),
+            (this, other) if *this == other => Ok(()),
+            (this, other) => Err(Error::Incompatible(alloc::format!(
+                "{:?} and {:?}",
+                this,
+                other
+            ))),
+        }
+    }
+}
+
+fn unify_formats(this: &mut [Format], other: Vec<Format>) -> Result<(), Error> {
+    if this.len() != other.len() {
+        return Err(Error::Incompatible(
+            "tuples of different lengths".to_owned(),
+        ));
+    }
+    this.iter_mut()
+        .zip(other)
+        .try_for_each(|(this, other)| this.unify(other))
+}
+
+fn unify_fields(this: &mut [Named<Format>], other: Vec<Named<Format>>) -> Result<(), Error> {
+    if this.len() != other.len() {
+        return Err(Error::Incompatible(
+            "structs of different lengths".to_owned(),
+        ));
+    }
+    this.iter_mut().zip(other).try_for_each(|(this, other)| {
+        if this.name != other.name {
+            return Err(Error::Incompatible(alloc::format!(
+                "fields {} and {}",
+                this.name,
+                other.name
+            )));
+        }
+        this.value.unify(other.value)
+    })
+}
+
+impl VariantFormat {
+    fn unify(&mut self, other: VariantFormat) -> Result<(), Error> {
+        match (self, other) {
+            (VariantFormat::Unit, VariantFormat::Unit) => Ok(()),
+            (VariantFormat::NewType(this), VariantFormat::NewType(other)) => this.unify(*other),
+            (VariantFormat::Tuple(this), VariantFormat::Tuple(other)) => unify_formats(this, other),
+            (VariantFormat::Struct(this), VariantFormat::Struct(other)) => {
+                unify_fields(this, other)
+            }
+            _ => Err(Error::Incompatible(
+                "variants of different kinds".to_owned(),
+            )),
+        }
+    }
+}
+
+impl ContainerFormat {
+    fn unify(&mut self, other: ContainerFormat) -> Result<(), Error> {
+        match (self, other) {
+            (ContainerFormat::UnitStruct, ContainerFormat::UnitStruct) => Ok(()),
+            (ContainerFormat::NewTypeStruct(this), ContainerFormat::NewTypeStruct(other)) => {
+                this.unify(*other)
+            }
+            (ContainerFormat::TupleStruct(this), ContainerFormat::TupleStruct(other)) => {
+                unify_formats(this, other)
+            }
+            (ContainerFormat::Struct(this), ContainerFormat::Struct(other)) => {
+                unify_fields(this, other)
+            }
+            (ContainerFormat::Enum(this), ContainerFormat::Enum(other)) => {
+                for (index, variant) in other {
+                    match this.get_mut(&index) {
+                        Some(this) if this.name == variant.name => {
+                            this.value.unify(variant.value)?
+                        }
+                        Some(_) => {
+                            return Err(Error::Incompatible(alloc::format!(
+                                "variants at index {}",
+                                index
+                            )))
+                        }
+                        None => {
+                            this.insert(index, variant);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(Error::Incompatible(
+                "containers of different kinds".to_owned(),
+            )),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[allow(unused)]
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        tags: Vec<(char, Option<i16>)>,
+        kind: Kind,
+        next: Option<Box<Record>>,
+    }
+
+    #[allow(unused)]
+    #[derive(Serialize, Deserialize)]
+    enum Kind {
+        Empty,
+        Value(f32),
+        Pair(u8, bool),
+        Named { bytes: serde_bytes::ByteBuf },
+    }
+
+    #[test]
+    fn test_trace() {
+        let schema = trace::<Record>().unwrap();
+        assert_eq!(schema.root, Format::TypeName("Record".into()));
+        fn named<T>(name: &str, value: T) -> Named<T> {
+            Named {
+                name: name.into(),
+                value,
+            }
+        }
+        assert_eq!(
+            schema.containers["Record"],
+            ContainerFormat::Struct(vec![
+                named("id", Format::U64),
+                named("name", Format::Str),
+                named(
+                    "tags",
+                    Format::Seq(Box::new(Format::Tuple(vec![
+                        Format::Char,
+                        Format::Option(Box::new(Format::I16))
+                    ])))
+                ),
+                named("kind", Format::TypeName("Kind".into())),
+                named(
+                    "next",
+                    Format::Option(Box::new(Format::TypeName("Record".into())))
+                ),
+            ])
+        );
+        assert_eq!(
+            schema.containers["Kind"],
+            ContainerFormat::Enum(BTreeMap::from([
+                (0, named("Empty", VariantFormat::Unit)),
+                (
+                    1,
+                    named("Value", VariantFormat::NewType(Box::new(Format::F32)))
+                ),
+                (
+                    2,
+                    named("Pair", VariantFormat::Tuple(vec![Format::U8, Format::Bool]))
+                ),
+                (
+                    3,
+                    named(
+                        "Named",
+                        VariantFormat::Struct(vec![named("bytes", Format::Bytes)])
+                    )
+                ),
+            ]))
+        );
+
+        let bytes = crate::to_bytes(&schema).unwrap();
+        let deserialized: Schema = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized, schema);
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = trace::<Record>().unwrap();
+        let record = Record {
+            id: 1,
+            name: "first".into(),
+            tags: vec![('a', None), ('b', Some(-3))],
+            kind: Kind::Pair(2, true),
+            next: Some(Box::new(Record {
+                id: 2,
+                name: "second".into(),
+                tags: vec![],
+                kind: Kind::Value(1.5),
+                next: None,
+            })),
+        };
+        let bytes = crate::to_bytes(&record).unwrap();
+        validate(&schema, &bytes).unwrap();
+
+        #[derive(Serialize)]
+        struct Wrong<'a> {
+            id: u64,
+            name: &'a str,
+            tags: Vec<(char, Option<i64>)>,
+        }
+        #[derive(Serialize)]
+        struct Outer<'a> {
+            id: u64,
+            name: &'a str,
+            tags: Vec<(char, Option<i16>)>,
+            kind: Kind,
+            next: Option<Wrong<'a>>,
+        }
+        let bytes = crate::to_bytes(&Outer {
+            id: 1,
+            name: "first",
+            tags: vec![],
+            kind: Kind::Empty,
+            next: Some(Wrong {
+                id: 2,
+                name: "second",
+                tags: vec![('a', None), ('b', Some(i64::MAX))],
+            }),
+        })
+        .unwrap();
+        let err = validate(&schema, &bytes).unwrap_err();
+        assert_eq!(err.path, ".next?.tags[1].1?");
+
+        assert!(validate(&schema, &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    mod v2 {
+        use super::*;
+
+        #[allow(unused)]
+        #[derive(Deserialize)]
+        pub struct Record {
+            pub id: u32,
+            pub label: String,
+            pub tags: Vec<(char, Option<i64>)>,
+            pub kind: Kind,
+            pub next: Option<Box<Record>>,
+        }
+
+        #[allow(unused)]
+        #[derive(Deserialize)]
+        pub enum Kind {
+            Empty,
+            Pair(u8, bool),
+            Value(f64),
+            Named { bytes: serde_bytes::ByteBuf },
+            Other,
+        }
+    }
+
+    #[test]
+    fn test_compatibility() {
+        assert_fully_compatible::<Record, Record>();
+
+        let compatibility = check_types::<Record, v2::Record>().unwrap();
+        let problem = |path: &str, kind| Incompatibility {
+            path: path.into(),
+            kind,
+        };
+        assert_eq!(
+            compatibility.backward,
+            vec![
+                problem(
+                    ".id",
+                    IncompatibilityKind::NarrowedNumber {
+                        written: Format::U64,
+                        read: Format::U32
+                    }
+                ),
+                problem("", IncompatibilityKind::MissingField("label".into())),
+                problem(
+                    ".kind",
+                    IncompatibilityKind::VariantIndexChanged {
+                        name: "Value".into(),
+                        written: 1,
+                        read: 2
+                    }
+                ),
+                problem(
+                    ".kind",
+                    IncompatibilityKind::VariantIndexChanged {
+                        name: "Pair".into(),
+                        written: 2,
+                        read: 1
+                    }
+                ),
+            ]
+        );
+        // widened numbers can't be read back by the old version, and it does not know the new variant.
+        assert!(compatibility.forward.contains(&problem(
+            ".tags[].1?",
+            IncompatibilityKind::NarrowedNumber {
+                written: Format::I64,
+                read: Format::I16
+            }
+        )));
+        assert!(compatibility.forward.contains(&problem(
+            ".kind",
+            IncompatibilityKind::UnknownVariant {
+                index: 4,
+                name: "Other".into()
+            }
+        )));
+    }
+}
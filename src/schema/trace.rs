@@ -0,0 +1,504 @@
+use alloc::{borrow::ToOwned, boxed::Box, collections::BTreeMap, vec, vec::Vec};
+use serde::de::{self, IntoDeserializer, Visitor};
+use serde::Deserialize;
+
+use super::{ContainerFormat, Error, Format, Named, Schema, VariantFormat};
+
+/// Past this many nested recursive occurences of types, tracing is considered endless.
+const MAX_RECURSION: usize = 64;
+
+/// Trace the wire format of `T` by deserializing it with a deserializer that records every request.
+///
+/// Sequences and maps are traced with a single element, options as `Some`, and every variant of an enum
+/// is reached by tracing the type again until none is left. Once inside a recursive occurence of a type,
+/// options are traced as `None`, sequences and maps as empty, and enums with their first variant.
+pub fn trace<'de, T>() -> Result<Schema, Error>
+where
+    T: Deserialize<'de>,
+{
+    let mut tracer = Tracer::default();
+    let mut root = Format::Unknown;
+    let mut reached = 0;
+    loop {
+        let mut format = Format::Unknown;
+        T::deserialize(TracerDeserializer::new(&mut tracer, &mut format))?;
+        root.unify(format)?;
+        match tracer.incomplete_enum() {
+            None => break,
+            Some(name) => {
+                let now_reached = tracer.reached_variants();
+                if now_reached == reached {
+                    return Err(Error::Incomplete(name.to_owned()));
+                }
+                reached = now_reached;
+            }
+        }
+    }
+    let containers = tracer
+        .containers
+        .into_iter()
+        .map(|(name, format)| (name.to_owned(), format))
+        .collect();
+    Ok(Schema { root, containers })
+}
+
+#[derive(Default)]
+struct Tracer {
+    containers: BTreeMap<&'static str, ContainerFormat>,
+    /// Number of variants of each traced enum.
+    enums: BTreeMap<&'static str, usize>,
+    /// Containers being traced.
+    stack: Vec<&'static str>,
+    /// Number of containers on the stack that were already on it.
+    recursion: usize,
+}
+
+impl Tracer {
+    fn enter(&mut self, name: &'static str) -> Result<(), Error> {
+        if self.stack.contains(&name) {
+            self.recursion += 1;
+            if self.recursion > MAX_RECURSION {
+                return Err(Error::Recursion(name.to_owned()));
+            }
+        }
+        self.stack.push(name);
+        Ok(())
+    }
+
+    fn exit(&mut self, name: &'static str, format: ContainerFormat) -> Result<(), Error> {
+        self.stack.pop();
+        if self.stack.contains(&name) {
+            self.recursion -= 1;
+        }
+        match self.containers.get_mut(name) {
+            Some(container) => container
+                .unify(format)
+                .map_err(|_| Error::Incompatible(name.to_owned())),
+            None => {
+                self.containers.insert(name, format);
+                Ok(())
+            }
+        }
+    }
+
+    fn is_recursing(&self) -> bool {
+        self.recursion > 0
+    }
+
+    fn reached_variants(&self) -> usize {
+        self.enums
+            .keys()
+            .map(|name| match self.containers.get(name) {
+                Some(ContainerFormat::Enum(variants)) => variants.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn incomplete_enum(&self) -> Option<&'static str> {
+        self.enums
+            .iter()
+            .find(|(name, count)| match self.containers.get(*name) {
+                Some(ContainerFormat::Enum(variants)) => variants.len() < **count,
+                _ => true,
+            })
+            .map(|(name, _)| *name)
+    }
+
+    /// The first variant not traced yet, or the first one when recursing.
+    fn next_variant(&self, name: &'static str, count: usize) -> u32 {
+        if self.is_recursing() {
+            return 0;
+        }
+        let traced = match self.containers.get(name) {
+            Some(ContainerFormat::Enum(variants)) => Some(variants),
+            _ => None,
+        };
+        (0..count as u32)
+            .find(|index| !traced.is_some_and(|variants| variants.contains_key(index)))
+            .unwrap_or(0)
+    }
+}
+
+struct TracerDeserializer<'a> {
+    tracer: &'a mut Tracer,
+    format: &'a mut Format,
+}
+
+impl<'a> TracerDeserializer<'a> {
+    fn new(tracer: &'a mut Tracer, format: &'a mut Format) -> Self {
+        TracerDeserializer { tracer, format }
+    }
+
+    fn record(&mut self, format: Format) -> Result<(), Error> {
+        self.format.unify(format)
+    }
+
+    fn visit_container<'de, V, F>(self, name: &'static str, visit: F) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+        F: FnOnce(&mut Tracer) -> Result<(V::Value, ContainerFormat), Error>,
+    {
+        self.format.unify(Format::TypeName(name.to_owned()))?;
+        self.tracer.enter(name)?;
+        let (value, format) = visit(self.tracer)?;
+        self.tracer.exit(name, format)?;
+        Ok(value)
+    }
+}
+
+macro_rules! trace_primitive {
+    ($fn_name:ident, $format:ident, $visit_fn:ident, $value:expr) => {
+        fn $fn_name<V>(mut self, visitor: V) -> Result<V::Value, Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.record(Format::$format)?;
+            visitor.$visit_fn($value)
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for TracerDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::DeserializeAny)
+    }
+
+    trace_primitive!(deserialize_bool, Bool, visit_bool, false);
+    trace_primitive!(deserialize_i8, I8, visit_i8, 0);
+    trace_primitive!(deserialize_i16, I16, visit_i16, 0);
+    trace_primitive!(deserialize_i32, I32, visit_i32, 0);
+    trace_primitive!(deserialize_i64, I64, visit_i64, 0);
+    trace_primitive!(deserialize_i128, I128, visit_i128, 0);
+    trace_primitive!(deserialize_u8, U8, visit_u8, 0);
+    trace_primitive!(deserialize_u16, U16, visit_u16, 0);
+    trace_primitive!(deserialize_u32, U32, visit_u32, 0);
+    trace_primitive!(deserialize_u64, U64, visit_u64, 0);
+    trace_primitive!(deserialize_u128, U128, visit_u128, 0);
+    trace_primitive!(deserialize_f32, F32, visit_f32, 0.0);
+    trace_primitive!(deserialize_f64, F64, visit_f64, 0.0);
+    trace_primitive!(deserialize_char, Char, visit_char, '\0');
+    trace_primitive!(deserialize_str, Str, visit_borrowed_str, "");
+    trace_primitive!(deserialize_string, Str, visit_borrowed_str, "");
+    trace_primitive!(deserialize_bytes, Bytes, visit_borrowed_bytes, &[]);
+    trace_primitive!(deserialize_byte_buf, Bytes, visit_borrowed_bytes, &[]);
+    trace_primitive!(deserialize_identifier, Str, visit_borrowed_str, "");
+
+    fn deserialize_unit<V>(mut self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.record(Format::Unit)?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut inner = Format::Unknown;
+        let value = if self.tracer.is_recursing() {
+            visitor.visit_none()?
+        } else {
+            visitor.visit_some(TracerDeserializer::new(self.tracer, &mut inner))?
+        };
+        self.format.unify(Format::Option(Box::new(inner)))?;
+        Ok(value)
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_container::<V, _>(name, |_| {
+            Ok((visitor.visit_unit()?, ContainerFormat::UnitStruct))
+        })
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_container::<V, _>(name, |tracer| {
+            let mut inner = Format::Unknown;
+            let value =
+                visitor.visit_newtype_struct(TracerDeserializer::new(tracer, &mut inner))?;
+            Ok((value, ContainerFormat::NewTypeStruct(Box::new(inner))))
+        })
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut elements = vec![Format::Unknown; usize::from(!self.tracer.is_recursing())];
+        let value = visitor.visit_seq(SeqTracer::new(self.tracer, &mut elements))?;
+        let element = elements.pop().unwrap_or(Format::Unknown);
+        self.format.unify(Format::Seq(Box::new(element)))?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut elements = vec![Format::Unknown; len];
+        let value = visitor.visit_seq(SeqTracer::new(self.tracer, &mut elements))?;
+        self.format.unify(Format::Tuple(elements))?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_container::<V, _>(name, |tracer| {
+            let mut elements = vec![Format::Unknown; len];
+            let value = visitor.visit_seq(SeqTracer::new(tracer, &mut elements))?;
+            Ok((value, ContainerFormat::TupleStruct(elements)))
+        })
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut key = Format::Unknown;
+        let mut value = Format::Unknown;
+        let remaining = !self.tracer.is_recursing();
+        let result = visitor.visit_map(MapTracer {
+            tracer: self.tracer,
+            key: &mut key,
+            value: &mut value,
+            remaining,
+        })?;
+        self.format.unify(Format::Map {
+            key: Box::new(key),
+            value: Box::new(value),
+        })?;
+        Ok(result)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.visit_container::<V, _>(name, |tracer| {
+            let (value, fields) = visit_fields(tracer, fields, visitor)?;
+            Ok((value, ContainerFormat::Struct(fields)))
+        })
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.enums.insert(name, variants.len());
+        let index = self.tracer.next_variant(name, variants.len());
+        let variant_name = variants
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| Error::Incomplete(name.to_owned()))?;
+        self.visit_container::<V, _>(name, |tracer| {
+            let mut format = None;
+            let value = visitor.visit_enum(EnumTracer {
+                tracer,
+                index,
+                format: &mut format,
+            })?;
+            let format = format.unwrap_or(VariantFormat::Unit);
+            let variant = Named {
+                name: variant_name.to_owned(),
+                value: format,
+            };
+            Ok((
+                value,
+                ContainerFormat::Enum(BTreeMap::from([(index, variant)])),
+            ))
+        })
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+fn visit_fields<'de, V>(
+    tracer: &mut Tracer,
+    fields: &'static [&'static str],
+    visitor: V,
+) -> Result<(V::Value, Vec<Named<Format>>), Error>
+where
+    V: Visitor<'de>,
+{
+    let mut formats = vec![Format::Unknown; fields.len()];
+    let value = visitor.visit_seq(SeqTracer::new(tracer, &mut formats))?;
+    let fields = fields
+        .iter()
+        .zip(formats)
+        .map(|(name, value)| Named {
+            name: (*name).to_owned(),
+            value,
+        })
+        .collect();
+    Ok((value, fields))
+}
+
+struct SeqTracer<'a> {
+    tracer: &'a mut Tracer,
+    elements: core::slice::IterMut<'a, Format>,
+}
+
+impl<'a> SeqTracer<'a> {
+    fn new(tracer: &'a mut Tracer, elements: &'a mut [Format]) -> Self {
+        SeqTracer {
+            tracer,
+            elements: elements.iter_mut(),
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqTracer<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.elements.next() {
+            Some(format) => seed
+                .deserialize(TracerDeserializer::new(self.tracer, format))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct MapTracer<'a> {
+    tracer: &'a mut Tracer,
+    key: &'a mut Format,
+    value: &'a mut Format,
+    remaining: bool,
+}
+
+impl<'de> de::MapAccess<'de> for MapTracer<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if !core::mem::take(&mut self.remaining) {
+            return Ok(None);
+        }
+        seed.deserialize(TracerDeserializer::new(self.tracer, self.key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(TracerDeserializer::new(self.tracer, self.value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(usize::from(self.remaining))
+    }
+}
+
+struct EnumTracer<'a> {
+    tracer: &'a mut Tracer,
+    index: u32,
+    format: &'a mut Option<VariantFormat>,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for EnumTracer<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumTracer<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        *self.format = Some(VariantFormat::Unit);
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        let mut inner = Format::Unknown;
+        let value = seed.deserialize(TracerDeserializer::new(self.tracer, &mut inner))?;
+        *self.format = Some(VariantFormat::NewType(Box::new(inner)));
+        Ok(value)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut elements = vec![Format::Unknown; len];
+        let value = visitor.visit_seq(SeqTracer::new(self.tracer, &mut elements))?;
+        *self.format = Some(VariantFormat::Tuple(elements));
+        Ok(value)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let (value, fields) = visit_fields(self.tracer, fields, visitor)?;
+        *self.format = Some(VariantFormat::Struct(fields));
+        Ok(value)
+    }
+}
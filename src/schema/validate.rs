@@ -0,0 +1,508 @@
+use alloc::{format, string::String, vec};
+use core::{
+    cell::RefCell,
+    fmt::{self, Display},
+};
+use serde::de::{self, DeserializeSeed, IgnoredAny, Visitor};
+
+use super::{ContainerFormat, Format, Named, Schema, VariantFormat};
+use crate::{de::Deserializer, error::EndOfBuff};
+
+/// Error returned by `validate`, with the path to the value that does not conform to the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Fields are prefixed by `.`, variants by `::`, sequence elements are `[index]`,
+    /// map entries `[index].key` and `[index].value` and optional values `?`.
+    pub path: String,
+    pub error: crate::de::Error<EndOfBuff>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            Display::fmt(&self.error, f)
+        } else {
+            f.write_fmt(format_args!("{}: {}", self.path, self.error))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ValidationError {}
+
+/// Check that `bytes` hold exactly one value conforming to `schema`, without the Rust type it was traced from.
+///
+/// Tags must be readable as the traced formats the same way the deserializer reads them, structs must contain
+/// every field and no other, and enum variants must exist in the schema.
+pub fn validate(schema: &Schema, bytes: &[u8]) -> Result<(), ValidationError> {
+    let validator = Validator {
+        schema,
+        path: RefCell::new(String::new()),
+    };
+    let mut reader = bytes;
+    let result = FormatSeed {
+        validator: &validator,
+        format: &schema.root,
+    }
+    .deserialize(&mut Deserializer::new(&mut reader))
+    .and_then(|()| {
+        if reader.is_empty() {
+            Ok(())
+        } else {
+            Err(de::Error::custom(format_args!(
+                "{} trailing bytes after the value",
+                reader.len()
+            )))
+        }
+    });
+    result.map_err(|error| ValidationError {
+        path: validator.path.into_inner(),
+        error,
+    })
+}
+
+struct Validator<'s> {
+    schema: &'s Schema,
+    /// Path of the value being validated, it is left untouched when an error is returned.
+    path: RefCell<String>,
+}
+
+impl Validator<'_> {
+    fn enter<T, E, F>(&self, segment: impl Display, f: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let len = {
+            let mut path = self.path.borrow_mut();
+            let len = path.len();
+            path.push_str(&format!("{}", segment));
+            len
+        };
+        let value = f()?;
+        self.path.borrow_mut().truncate(len);
+        Ok(value)
+    }
+}
+
+struct FormatSeed<'v, 's> {
+    validator: &'v Validator<'s>,
+    format: &'s Format,
+}
+
+impl<'de> DeserializeSeed<'de> for FormatSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let expected = Expected(self.format);
+        match self.format {
+            Format::Unknown => deserializer.deserialize_ignored_any(IgnoredAny).map(drop),
+            Format::TypeName(name) => {
+                let container = self
+                    .validator
+                    .schema
+                    .containers
+                    .get(name)
+                    .ok_or_else(|| de::Error::custom(format_args!("unknown type {}", name)))?;
+                ContainerSeed {
+                    validator: self.validator,
+                    container,
+                }
+                .deserialize(deserializer)
+            }
+            Format::Unit => deserializer.deserialize_unit(expected),
+            Format::Bool => deserializer.deserialize_bool(expected),
+            Format::I8 => deserializer.deserialize_i8(expected),
+            Format::I16 => deserializer.deserialize_i16(expected),
+            Format::I32 => deserializer.deserialize_i32(expected),
+            Format::I64 => deserializer.deserialize_i64(expected),
+            Format::I128 => deserializer.deserialize_i128(expected),
+            Format::U8 => deserializer.deserialize_u8(expected),
+            Format::U16 => deserializer.deserialize_u16(expected),
+            Format::U32 => deserializer.deserialize_u32(expected),
+            Format::U64 => deserializer.deserialize_u64(expected),
+            Format::U128 => deserializer.deserialize_u128(expected),
+            Format::F32 => deserializer.deserialize_f32(expected),
+            Format::F64 => deserializer.deserialize_f64(expected),
+            Format::Char => deserializer.deserialize_char(expected),
+            Format::Str => deserializer.deserialize_str(expected),
+            Format::Bytes => deserializer.deserialize_bytes(expected),
+            Format::Option(_) | Format::Seq(_) | Format::Map { .. } => {
+                let visitor = ValueVisitor {
+                    validator: self.validator,
+                    format: self.format,
+                };
+                match self.format {
+                    Format::Option(_) => deserializer.deserialize_option(visitor),
+                    Format::Seq(_) => deserializer.deserialize_seq(visitor),
+                    _ => deserializer.deserialize_map(visitor),
+                }
+            }
+            Format::Tuple(elements) => deserializer.deserialize_tuple(
+                elements.len(),
+                ElementsVisitor {
+                    validator: self.validator,
+                    elements,
+                },
+            ),
+        }
+    }
+}
+
+/// Visitor accepting any primitive value, the deserializer already checked the tag against the requested format.
+struct Expected<'s>(&'s Format);
+
+impl<'de> Visitor<'de> for Expected<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_fmt(format_args!("a value of format {:?}", self.0))
+    }
+
+    fn visit_bool<E: de::Error>(self, _v: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _v: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i128<E: de::Error>(self, _v: i128) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _v: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u128<E: de::Error>(self, _v: u128) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _v: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_char<E: de::Error>(self, _v: char) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _v: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_bytes<E: de::Error>(self, _v: &[u8]) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Visitor for options, sequences and maps.
+struct ValueVisitor<'v, 's> {
+    validator: &'v Validator<'s>,
+    format: &'s Format,
+}
+
+impl<'v, 's> ValueVisitor<'v, 's> {
+    fn seed(&self, format: &'s Format) -> FormatSeed<'v, 's> {
+        FormatSeed {
+            validator: self.validator,
+            format,
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for ValueVisitor<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_fmt(format_args!("a value of format {:?}", self.format))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let Format::Option(inner) = self.format else {
+            return Err(de::Error::invalid_type(de::Unexpected::Option, &self));
+        };
+        self.validator
+            .enter("?", || self.seed(inner).deserialize(deserializer))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let Format::Seq(element) = self.format else {
+            return Err(de::Error::invalid_type(de::Unexpected::Seq, &self));
+        };
+        let mut index = 0;
+        while self
+            .validator
+            .enter(format_args!("[{}]", index), || {
+                seq.next_element_seed(self.seed(element))
+            })?
+            .is_some()
+        {
+            index += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let Format::Map { key, value } = self.format else {
+            return Err(de::Error::invalid_type(de::Unexpected::Map, &self));
+        };
+        let mut index = 0;
+        while self
+            .validator
+            .enter(format_args!("[{}].key", index), || {
+                map.next_key_seed(self.seed(key))
+            })?
+            .is_some()
+        {
+            self.validator
+                .enter(format_args!("[{}].value", index), || {
+                    map.next_value_seed(self.seed(value))
+                })?;
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Visitor for tuples, tuple structs and tuple variants.
+struct ElementsVisitor<'v, 's> {
+    validator: &'v Validator<'s>,
+    elements: &'s [Format],
+}
+
+impl<'de> Visitor<'de> for ElementsVisitor<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_fmt(format_args!("a tuple of {} elements", self.elements.len()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        for (index, format) in self.elements.iter().enumerate() {
+            let seed = FormatSeed {
+                validator: self.validator,
+                format,
+            };
+            self.validator
+                .enter(format_args!(".{}", index), || seq.next_element_seed(seed))?
+                .ok_or_else(|| de::Error::invalid_length(index, &self))?;
+        }
+        match seq.next_element::<IgnoredAny>()? {
+            Some(_) => Err(de::Error::invalid_length(self.elements.len() + 1, &self)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Visitor for structs and struct variants.
+struct FieldsVisitor<'v, 's> {
+    validator: &'v Validator<'s>,
+    fields: &'s [Named<Format>],
+}
+
+impl<'de> Visitor<'de> for FieldsVisitor<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a struct")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut seen = vec![false; self.fields.len()];
+        while let Some(key) = map.next_key::<String>()? {
+            let index = self
+                .fields
+                .iter()
+                .position(|field| field.name == key)
+                .ok_or_else(|| de::Error::custom(format_args!("unknown field {}", key)))?;
+            if seen[index] {
+                return Err(de::Error::custom(format_args!("duplicate field {}", key)));
+            }
+            seen[index] = true;
+            let seed = FormatSeed {
+                validator: self.validator,
+                format: &self.fields[index].value,
+            };
+            self.validator
+                .enter(format_args!(".{}", key), || map.next_value_seed(seed))?;
+        }
+        match seen.iter().position(|seen| !seen) {
+            Some(missing) => Err(de::Error::custom(format_args!(
+                "missing field {}",
+                self.fields[missing].name
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+struct ContainerSeed<'v, 's> {
+    validator: &'v Validator<'s>,
+    container: &'s ContainerFormat,
+}
+
+impl<'de> DeserializeSeed<'de> for ContainerSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let validator = self.validator;
+        match self.container {
+            ContainerFormat::UnitStruct => {
+                deserializer.deserialize_unit_struct("", Expected(&Format::Unit))
+            }
+            ContainerFormat::NewTypeStruct(inner) => {
+                deserializer.deserialize_newtype_struct("", NewTypeVisitor { validator, inner })
+            }
+            ContainerFormat::TupleStruct(elements) => deserializer.deserialize_tuple_struct(
+                "",
+                elements.len(),
+                ElementsVisitor {
+                    validator,
+                    elements,
+                },
+            ),
+            ContainerFormat::Struct(fields) => {
+                deserializer.deserialize_struct("", &[], FieldsVisitor { validator, fields })
+            }
+            ContainerFormat::Enum(variants) => deserializer.deserialize_enum(
+                "",
+                &[],
+                EnumVisitor {
+                    validator,
+                    variants,
+                },
+            ),
+        }
+    }
+}
+
+struct NewTypeVisitor<'v, 's> {
+    validator: &'v Validator<'s>,
+    inner: &'s Format,
+}
+
+impl<'de> Visitor<'de> for NewTypeVisitor<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a newtype struct")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        FormatSeed {
+            validator: self.validator,
+            format: self.inner,
+        }
+        .deserialize(deserializer)
+    }
+}
+
+type Variants = alloc::collections::BTreeMap<u32, Named<VariantFormat>>;
+
+struct EnumVisitor<'v, 's> {
+    validator: &'v Validator<'s>,
+    variants: &'s Variants,
+}
+
+impl<'de> Visitor<'de> for EnumVisitor<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an enum")
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<(), A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        use de::VariantAccess;
+        let validator = self.validator;
+        let (variant, access) = data.variant_seed(VariantSeed(self.variants))?;
+        validator.enter(format_args!("::{}", variant.name), || {
+            match &variant.value {
+                VariantFormat::Unit => access.unit_variant(),
+                VariantFormat::NewType(format) => {
+                    access.newtype_variant_seed(FormatSeed { validator, format })
+                }
+                VariantFormat::Tuple(elements) => access.tuple_variant(
+                    elements.len(),
+                    ElementsVisitor {
+                        validator,
+                        elements,
+                    },
+                ),
+                VariantFormat::Struct(fields) => {
+                    access.struct_variant(&[], FieldsVisitor { validator, fields })
+                }
+            }
+        })
+    }
+}
+
+/// Find the variant from its identifier, either an index or a name.
+struct VariantSeed<'s>(&'s Variants);
+
+impl<'de, 's> DeserializeSeed<'de> for VariantSeed<'s> {
+    type Value = &'s Named<VariantFormat>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de, 's> Visitor<'de> for VariantSeed<'s> {
+    type Value = &'s Named<VariantFormat>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_fmt(format_args!("one of {} variants", self.0.len()))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        u32::try_from(v)
+            .ok()
+            .and_then(|index| self.0.get(&index))
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        self.0
+            .values()
+            .find(|variant| variant.name == v)
+            .ok_or_else(|| E::custom(format_args!("unknown variant {}", v)))
+    }
+}
@@ -0,0 +1,216 @@
+//! Encrypted and authenticated rsbin data, with XChaCha20-Poly1305.
+//!
+//! Sealed data is a header followed by the encrypted encoding of the value and its authentication tag:
+//!
+//! | `SEALED_MAGIC` | key id: u32 BE | nonce: 24 bytes | ciphertext | tag: 16 bytes |
+//!
+//! The header is authenticated with the ciphertext, nothing is decrypted nor decoded before the whole
+//! data is authenticated.
+
+use alloc::vec::Vec;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use core::fmt::{self, Debug, Display};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
+
+use crate::{de, error::EndOfBuff, ser};
+
+/// First bytes of sealed data.
+pub const SEALED_MAGIC: [u8; 4] = *b"rsbs";
+
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = SEALED_MAGIC.len() + 4 + NONCE_LEN;
+
+/// Secret key and the id written in the header of the data it seals.
+#[derive(Clone)]
+pub struct Key {
+    id: u32,
+    secret: [u8; 32],
+}
+
+impl Key {
+    pub fn new(id: u32, secret: [u8; 32]) -> Self {
+        Key { id, secret }
+    }
+
+    /// New key with a random secret.
+    pub fn generate(id: u32) -> Self {
+        Key {
+            id,
+            secret: XChaCha20Poly1305::generate_key(&mut OsRng).into(),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn secret(&self) -> &[u8; 32] {
+        &self.secret
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.secret.into())
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Serialize(ser::Error<io::Error>),
+    Deserialize(de::Error<EndOfBuff>),
+    /// The data is too short or doesn't start with `SEALED_MAGIC`.
+    InvalidHeader,
+    /// None of the keys has the id of the key the data was sealed with.
+    UnknownKey(u32),
+    /// The data was modified or sealed with another key of the same id.
+    Tampered,
+    /// The value is too long to be encrypted.
+    TooLarge,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serialize(err) => Display::fmt(err, f),
+            Error::Deserialize(err) => Display::fmt(err, f),
+            Error::InvalidHeader => f.write_str("Sealed data must start with \"rsbs\"."),
+            Error::UnknownKey(id) => f.write_fmt(format_args!("No key with the id {}.", id)),
+            Error::Tampered => f.write_str("Sealed data failed authentication."),
+            Error::TooLarge => f.write_str("The value is too long to be sealed."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ser::Error<io::Error>> for Error {
+    fn from(value: ser::Error<io::Error>) -> Self {
+        Error::Serialize(value)
+    }
+}
+
+impl From<de::Error<EndOfBuff>> for Error {
+    fn from(value: de::Error<EndOfBuff>) -> Self {
+        Error::Deserialize(value)
+    }
+}
+
+/// Serialize `value` then encrypt it with `key` and a random nonce.
+pub fn seal<T>(value: &T, key: &Key) -> Result<Vec<u8>, Error>
+where
+    T: ?Sized + Serialize,
+{
+    seal_bytes(&crate::to_bytes(value)?, key)
+}
+
+/// Encrypt already encoded data with `key` and a random nonce.
+pub fn seal_bytes(bytes: &[u8], key: &Key) -> Result<Vec<u8>, Error> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = Vec::with_capacity(HEADER_LEN + bytes.len() + 16);
+    sealed.extend_from_slice(&SEALED_MAGIC);
+    sealed.extend_from_slice(&key.id.to_be_bytes());
+    sealed.extend_from_slice(&nonce);
+    let payload = Payload {
+        msg: bytes,
+        aad: &sealed,
+    };
+    let ciphertext = key
+        .cipher()
+        .encrypt(&nonce, payload)
+        .map_err(|_| Error::TooLarge)?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Id of the key `sealed` was sealed with.
+pub fn key_id(sealed: &[u8]) -> Result<u32, Error> {
+    if sealed.len() < HEADER_LEN || sealed[..4] != SEALED_MAGIC {
+        return Err(Error::InvalidHeader);
+    }
+    Ok(u32::from_be_bytes(sealed[4..8].try_into().unwrap()))
+}
+
+/// Authenticate and decrypt `sealed` with the key of `keys` that has its id, giving back the encoded data.
+pub fn open_bytes(sealed: &[u8], keys: &[Key]) -> Result<Vec<u8>, Error> {
+    let id = key_id(sealed)?;
+    let key = keys
+        .iter()
+        .find(|key| key.id == id)
+        .ok_or(Error::UnknownKey(id))?;
+    let (header, ciphertext) = sealed.split_at(HEADER_LEN);
+    let nonce = XNonce::from_slice(&header[8..]);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    key.cipher()
+        .decrypt(nonce, payload)
+        .map_err(|_| Error::Tampered)
+}
+
+/// Authenticate, decrypt then deserialize `sealed`, see `open_bytes`.
+pub fn open<T: DeserializeOwned>(sealed: &[u8], keys: &[Key]) -> Result<T, Error> {
+    Ok(crate::from_bytes(&open_bytes(sealed, keys)?)?)
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Credentials {
+        user: String,
+        token: String,
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let credentials = Credentials {
+            user: "ada".into(),
+            token: "s3cr3t".into(),
+        };
+        let old = Key::generate(1);
+        let current = Key::generate(2);
+        let keys = [old.clone(), current.clone()];
+
+        let sealed = seal(&credentials, &current).unwrap();
+        assert_eq!(key_id(&sealed).unwrap(), 2);
+        assert!(!sealed.windows(6).any(|window| window == b"s3cr3t"));
+        assert_eq!(open::<Credentials>(&sealed, &keys).unwrap(), credentials);
+
+        assert!(matches!(
+            open::<Credentials>(&sealed, &[old]),
+            Err(Error::UnknownKey(2))
+        ));
+        assert!(matches!(
+            open::<Credentials>(&sealed, &[Key::new(2, [0; 32])]),
+            Err(Error::Tampered)
+        ));
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            open::<Credentials>(&tampered, &keys),
+            Err(Error::Tampered)
+        ));
+        let other = seal(&42u8, &current).unwrap();
+        assert!(matches!(
+            open::<Credentials>(&other, &keys),
+            Err(Error::Deserialize(_))
+        ));
+        assert!(matches!(
+            open::<Credentials>(&sealed[..10], &keys),
+            Err(Error::InvalidHeader)
+        ));
+    }
+}
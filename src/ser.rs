@@ -1,34 +1,157 @@
+#[cfg(feature = "alloc")]
+use crate::error::RWError;
 use crate::error::{EndOfBuff, NoRWError};
+#[cfg(feature = "std")]
+use crate::shared::{CANONICAL as SHARED_CANONICAL, SHARED};
+use crate::stream::STREAMED_BYTES;
 use crate::tag::{Tag, UNSIZED_STRING_END_MARKER};
 use crate::utils::write::{BuffWriter, DummyWriter, Write};
 #[cfg(feature = "alloc")]
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
 use core::fmt;
-use serde::{ser, serde_if_integer128, Serialize};
+use serde::{ser, Serialize};
 #[cfg(feature = "std")]
 use std::io;
 
 pub type Error<We = NoRWError> = crate::error::SerError<We>;
 pub type Result<T, We = NoRWError> = core::result::Result<T, Error<We>>;
 
+/// How enum variants are identified on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum VariantEncoding {
+    /// The variant index, the most compact but reordering variants breaks existing data.
+    #[default]
+    Index,
+    /// The variant name, so variants can be reordered.
+    Name,
+    /// Both the index and the name, decoding uses the name.
+    NameAndIndex,
+}
+
 pub struct Serializer<W> {
     writer: W,
+    variant_encoding: VariantEncoding,
+    // the next tuple is the content of a `StreamedBytes`
+    streamed_bytes: bool,
+    // byte arrays are chunks of a `StreamedBytes` and are written without tag nor length
+    raw_bytes: bool,
+    canonical: bool,
+    // indexes of the type names written so far, when they are written
+    #[cfg(feature = "alloc")]
+    type_names: Option<BTreeMap<&'static str, u32>>,
 }
 
 impl<W: Write> Serializer<W> {
     pub fn new(writer: W) -> Self {
-        Serializer { writer }
+        Serializer {
+            writer,
+            variant_encoding: VariantEncoding::default(),
+            streamed_bytes: false,
+            raw_bytes: false,
+            canonical: false,
+            #[cfg(feature = "alloc")]
+            type_names: None,
+        }
+    }
+
+    pub fn with_variant_encoding(mut self, variant_encoding: VariantEncoding) -> Self {
+        self.variant_encoding = variant_encoding;
+        self
     }
 
-    pub fn to_writer<T: ?Sized>(value: &T, writer: W) -> Result<usize, W::Error>
+    /// Encode values canonically, so equal values always give the same bytes.
+    ///
+    /// Numbers always use the smallest tag they fit in, NaNs are normalized, sequences and strings
+    /// of unknown length are buffered to be written with their length and map entries are sorted
+    /// by the bytes of their encoded key.
+    #[cfg(feature = "alloc")]
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Write the name of structs, newtypes and enums before their tag, so decoding them as another type fails.
+    ///
+    /// Each name is written once, then referred to by its index. Canonical encoding writes names in full
+    /// every time, as buffered values are reordered.
+    #[cfg(feature = "alloc")]
+    pub fn with_type_names(mut self, type_names: bool) -> Self {
+        self.type_names = type_names.then(BTreeMap::new);
+        self
+    }
+
+    /// Forget the type names written so far, so the next value writes its names in full.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn reset_type_names(&mut self) {
+        if let Some(type_names) = &mut self.type_names {
+            type_names.clear();
+        }
+    }
+
+    pub fn to_writer<T>(value: &T, writer: W) -> Result<usize, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let mut serializer = Serializer::new(writer);
 
         value.serialize(&mut serializer)
     }
 
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Start a string written chunk by chunk, without needing to know its total length upfront.
+    ///
+    /// Chunks are concatenated when decoding, so a char can be split between two chunks.
+    pub fn serialize_chunked_str(&mut self) -> Result<ChunkedSerializer<'_, W>, W::Error> {
+        self.start_chunks(Tag::ChunkedString)
+    }
+
+    /// Start a byte array written chunk by chunk, without needing to know its total length upfront.
+    pub fn serialize_chunked_bytes(&mut self) -> Result<ChunkedSerializer<'_, W>, W::Error> {
+        self.start_chunks(Tag::ChunkedBytes)
+    }
+
+    fn start_chunks(&mut self, tag: Tag) -> Result<ChunkedSerializer<'_, W>, W::Error> {
+        #[cfg(feature = "alloc")]
+        if self.canonical {
+            // chunks are joined and written with their length once finished.
+            let tag = match tag {
+                Tag::ChunkedString => Tag::String,
+                _ => Tag::Bytes,
+            };
+            let mut chunked_serializer = ChunkedSerializer::new(self, 0);
+            chunked_serializer.buffer = Some((tag, Vec::new()));
+            return Ok(chunked_serializer);
+        }
+        let wb = self.write_tag(tag)?;
+        Ok(ChunkedSerializer::new(self, wb))
+    }
+
+    fn compact_nums(&self) -> bool {
+        cfg!(feature = "compact-nums") || self.canonical
+    }
+
+    /// Serialize a value in a new buffer, with the same configuration.
+    #[cfg(feature = "alloc")]
+    fn serialize_to_buffer<T>(&self, value: &T) -> Result<Vec<u8>, W::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut buffer = Vec::new();
+        let mut serializer = Serializer::new(&mut buffer)
+            .with_variant_encoding(self.variant_encoding)
+            .with_canonical(self.canonical)
+            .with_type_names(self.type_names.is_some());
+        value.serialize(&mut serializer).map_err(buffer_error)?;
+        Ok(buffer)
+    }
+
     fn write_byte(&mut self, byte: u8) -> Result<usize, W::Error> {
         self.writer.write_byte(byte).map_err(Into::into)
     }
@@ -41,13 +164,9 @@ impl<W: Write> Serializer<W> {
         self.write_byte(tag.into())
     }
 
-    fn write_tag_then_serialize<T: ?Sized>(
-        &mut self,
-        tag: Tag,
-        value: &T,
-    ) -> Result<usize, W::Error>
+    fn write_tag_then_serialize<T>(&mut self, tag: Tag, value: &T) -> Result<usize, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let mut wb = self.write_tag(tag)?;
         wb += value.serialize(self)?;
@@ -70,23 +189,68 @@ impl<W: Write> Serializer<W> {
         Ok(wb)
     }
 
-    fn write_tag_then_variant(&mut self, tag: Tag, variant_index: u32) -> Result<usize, W::Error> {
-        self.write_tag_then_serialize(tag, &variant_index)
+    #[cfg(feature = "alloc")]
+    fn write_type_name(&mut self, name: &'static str) -> Result<usize, W::Error> {
+        let Some(type_names) = &mut self.type_names else {
+            return Ok(0);
+        };
+        if !self.canonical {
+            let next_index = type_names.len() as u32;
+            let index = *type_names.entry(name).or_insert(next_index);
+            if index != next_index {
+                return self.write_tag_then_serialize(Tag::TypeName, &index);
+            }
+        }
+        self.write_tag_then_serialize(Tag::TypeName, name)
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn write_type_name(&mut self, _name: &'static str) -> Result<usize, W::Error> {
+        Ok(0)
+    }
+
+    fn write_tag_then_variant(
+        &mut self,
+        tag: Tag,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<usize, W::Error> {
+        let wb = self.write_type_name(name)?;
+        let variant_wb = match self.variant_encoding {
+            VariantEncoding::Index => self.write_tag_then_serialize(tag, &variant_index),
+            VariantEncoding::Name => self.write_tag_then_serialize(tag, variant),
+            VariantEncoding::NameAndIndex => {
+                self.write_tag_then_serialize(tag, &(variant_index, variant))
+            }
+        }?;
+        Ok(wb + variant_wb)
+    }
+}
+
+/// Writing to a `Vec` can't fail, only custom errors are left.
+#[cfg(feature = "alloc")]
+fn buffer_error<E: RWError, We: RWError>(err: Error<E>) -> Error<We> {
+    match err {
+        Error::Custom(err) => Error::Custom(err),
+        Error::LenMismatch { declared, written } => Error::LenMismatch { declared, written },
+        Error::DuplicateKey => Error::DuplicateKey,
+        Error::WriteError(err) => ser::Error::custom(err),
     }
 }
 
-pub fn to_writer<W, T: ?Sized>(value: &T, writer: W) -> Result<usize, W::Error>
+pub fn to_writer<W, T>(value: &T, writer: W) -> Result<usize, W::Error>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
     W: Write,
 {
     Serializer::to_writer(value, writer)
 }
 
 #[cfg(all(feature = "alloc", not(feature = "std")))]
-pub fn to_bytes<T: ?Sized>(value: &T) -> Result<Vec<u8>>
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
 {
     let mut output = Vec::new();
     Serializer::to_writer(value, &mut output)?;
@@ -94,27 +258,69 @@ where
 }
 
 #[cfg(feature = "std")]
-pub fn to_bytes<T: ?Sized>(value: &T) -> Result<Vec<u8>, io::Error>
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>, io::Error>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
 {
     let mut output = Vec::new();
     Serializer::to_writer(value, &mut output)?;
     Ok(output)
 }
 
-pub fn to_buff<'a, T: ?Sized>(value: &T, buff: &'a mut [u8]) -> Result<BuffWriter<'a>, EndOfBuff>
+/// Serialize `value` into `buffer` after clearing it, so its capacity is reused from a call to the next.
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+pub fn to_vec_into<T>(value: &T, buffer: &mut Vec<u8>) -> Result<usize>
+where
+    T: ?Sized + Serialize,
+{
+    buffer.clear();
+    Serializer::to_writer(value, buffer)
+}
+
+/// Serialize `value` into `buffer` after clearing it, so its capacity is reused from a call to the next.
+#[cfg(feature = "std")]
+pub fn to_vec_into<T>(value: &T, buffer: &mut Vec<u8>) -> Result<usize, io::Error>
+where
+    T: ?Sized + Serialize,
+{
+    buffer.clear();
+    Serializer::to_writer(value, buffer)
+}
+
+/// Serialize `value` in its canonical encoding, see `Serializer::with_canonical`.
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+pub fn to_canonical_bytes<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut output = Vec::new();
+    value.serialize(&mut Serializer::new(&mut output).with_canonical(true))?;
+    Ok(output)
+}
+
+/// Serialize `value` in its canonical encoding, see `Serializer::with_canonical`.
+#[cfg(feature = "std")]
+pub fn to_canonical_bytes<T>(value: &T) -> Result<Vec<u8>, io::Error>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
+{
+    let mut output = Vec::new();
+    value.serialize(&mut Serializer::new(&mut output).with_canonical(true))?;
+    Ok(output)
+}
+
+pub fn to_buff<'a, T>(value: &T, buff: &'a mut [u8]) -> Result<BuffWriter<'a>, EndOfBuff>
+where
+    T: ?Sized + Serialize,
 {
     let mut buff_writer = BuffWriter::new(buff);
     Serializer::to_writer(value, &mut buff_writer)?;
     Ok(buff_writer)
 }
 
-pub fn get_serialized_size<T: ?Sized>(value: &T) -> Result<usize>
+pub fn get_serialized_size<T>(value: &T) -> Result<usize>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
 {
     Serializer::to_writer(value, DummyWriter)
 }
@@ -128,17 +334,39 @@ macro_rules! implement_number {
     // for compactness the "compact-nums" feature allow number to be serialized in the smallest format they can fit in
     // for exemple a u64 with a value that can fit in a u16 will be serialized as a u16
     ($fn_name:ident, $t:ident, $tag:expr, $sub:ty, $forward_fn:ident) => {
-        #[cfg(feature = "compact-nums")]
         fn $fn_name(self, value: $t) -> Result<Self::Ok, W::Error> {
-            if let Ok(value) = <$sub>::try_from(value) {
-                self.$forward_fn(value)
-            } else {
-                self.write_tag_then_bytes(Tag::U64, &value.to_be_bytes())
+            if self.compact_nums() {
+                if let Ok(value) = <$sub>::try_from(value) {
+                    return self.$forward_fn(value);
+                }
             }
+            self.write_tag_then_bytes($tag, &value.to_be_bytes())
         }
+    };
+    // in canonical mode 128 bits integers are also compacted
+    ($fn_name:ident, $t:ident, $tag:expr, $sub:ty, $forward_fn:ident, canonical) => {
+        fn $fn_name(self, value: $t) -> Result<Self::Ok, W::Error> {
+            if self.canonical {
+                if let Ok(value) = <$sub>::try_from(value) {
+                    return self.$forward_fn(value);
+                }
+            }
+            self.write_tag_then_bytes($tag, &value.to_be_bytes())
+        }
+    };
+}
 
-        #[cfg(not(feature = "compact-nums"))]
-        implement_number! { $fn_name, $t, $tag }
+macro_rules! implement_float {
+    ($fn_name:ident, $t:ident, $tag:expr) => {
+        fn $fn_name(self, value: $t) -> Result<Self::Ok, W::Error> {
+            // every NaN is the same value, only keep one bit pattern.
+            let value = if self.canonical && value.is_nan() {
+                $t::NAN
+            } else {
+                value
+            };
+            self.write_tag_then_bytes($tag, &value.to_be_bytes())
+        }
     };
 }
 
@@ -174,13 +402,25 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     implement_number!(serialize_u16, u16, Tag::U16, u8, serialize_u8);
     implement_number!(serialize_u32, u32, Tag::U32, u16, serialize_u16);
     implement_number!(serialize_u64, u64, Tag::U64, u32, serialize_u32);
-    implement_number!(serialize_f32, f32, Tag::F32);
-    implement_number!(serialize_f64, f64, Tag::F64);
-
-    serde_if_integer128! {
-        implement_number!(serialize_i128, i128, Tag::I128);
-        implement_number!(serialize_u128, u128, Tag::U128);
-    }
+    implement_float!(serialize_f32, f32, Tag::F32);
+    implement_float!(serialize_f64, f64, Tag::F64);
+
+    implement_number!(
+        serialize_i128,
+        i128,
+        Tag::I128,
+        i64,
+        serialize_i64,
+        canonical
+    );
+    implement_number!(
+        serialize_u128,
+        u128,
+        Tag::U128,
+        u64,
+        serialize_u64,
+        canonical
+    );
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, W::Error> {
         let mut buff = [0; 4];
@@ -193,6 +433,9 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, W::Error> {
+        if self.raw_bytes {
+            return self.write_bytes(v);
+        }
         self.write_tag_then_seq(Tag::Bytes, v)
     }
 
@@ -200,9 +443,9 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         self.write_tag(Tag::None)
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, W::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.write_tag_then_serialize(Tag::Some, value)
     }
@@ -211,50 +454,65 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         self.write_tag(Tag::Unit)
     }
 
-    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, W::Error> {
-        self.write_tag(Tag::UnitStruct)
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, W::Error> {
+        let wb = self.write_type_name(name)?;
+        Ok(wb + self.write_tag(Tag::UnitStruct)?)
     }
 
     fn serialize_unit_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, W::Error> {
-        self.write_tag_then_variant(Tag::UnitVariant, variant_index)
+        self.write_tag_then_variant(Tag::UnitVariant, name, variant_index, variant)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.write_tag_then_serialize(Tag::NewTypeStruct, value)
+        if name == STREAMED_BYTES {
+            self.streamed_bytes = true;
+            return value.serialize(self);
+        }
+        let wb = self.write_type_name(name)?;
+        Ok(wb + self.write_tag_then_serialize(Tag::NewTypeStruct, value)?)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let mut wb = self.write_tag_then_variant(Tag::NewTypeVariant, variant_index)?;
+        #[cfg(feature = "std")]
+        if self.canonical && name == SHARED {
+            return Err(ser::Error::custom(SHARED_CANONICAL));
+        }
+        let mut wb =
+            self.write_tag_then_variant(Tag::NewTypeVariant, name, variant_index, variant)?;
         wb += value.serialize(self)?;
         Ok(wb)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, W::Error> {
+        #[cfg(feature = "alloc")]
+        if self.canonical && len.is_none() {
+            return Ok(SeqSerializer::buffered(self, Buffer::Seq(0, Vec::new())));
+        }
         match len {
             Some(len) => {
                 let written_bytes = self.write_tag_then_len(Tag::Seq, len)?;
-                Ok(SeqSerializer::new(self, written_bytes, true))
+                Ok(SeqSerializer::sized(self, written_bytes, len, false))
             }
             None => {
                 let written_bytes = self.write_tag(Tag::UnsizedSeq)?;
@@ -264,35 +522,50 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, W::Error> {
+        if core::mem::take(&mut self.streamed_bytes) {
+            // a streamed byte array is a tuple of its length followed by its chunks,
+            // written as a regular byte array.
+            let wb = self.write_tag(Tag::Bytes)?;
+            let mut seq_serializer = SeqSerializer::new(self, wb, true);
+            seq_serializer.streamed_bytes = true;
+            return Ok(seq_serializer);
+        }
         let wb = self.write_tag_then_len(Tag::Tuple, len)?;
-        Ok(SeqSerializer::new(self, wb, true))
+        Ok(SeqSerializer::sized(self, wb, len, false))
     }
 
     fn serialize_tuple_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, W::Error> {
-        let wb = self.write_tag_then_len(Tag::TupleStruct, len)?;
-        Ok(SeqSerializer::new(self, wb, true))
+        let mut wb = self.write_type_name(name)?;
+        wb += self.write_tag_then_len(Tag::TupleStruct, len)?;
+        Ok(SeqSerializer::sized(self, wb, len, false))
     }
 
     fn serialize_tuple_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
-        _len: usize,
+        variant: &'static str,
+        len: usize,
     ) -> Result<Self::SerializeTupleVariant, W::Error> {
-        let wb = self.write_tag_then_variant(Tag::TupleVariant, variant_index)?;
-        Ok(SeqSerializer::new(self, wb, true))
+        let mut wb =
+            self.write_tag_then_variant(Tag::TupleVariant, name, variant_index, variant)?;
+        wb += len.serialize(&mut *self)?;
+        Ok(SeqSerializer::sized(self, wb, len, false))
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, W::Error> {
+        #[cfg(feature = "alloc")]
+        if self.canonical {
+            return Ok(SeqSerializer::buffered(self, Buffer::Map(None, Vec::new())));
+        }
         match len {
             Some(len) => {
                 let wb = self.write_tag_then_len(Tag::Map, len)?;
-                Ok(SeqSerializer::new(self, wb, true))
+                Ok(SeqSerializer::sized(self, wb, len, true))
             }
             None => {
                 let written_bytes = self.write_tag(Tag::UnsizedMap)?;
@@ -303,34 +576,44 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 
     fn serialize_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, W::Error> {
-        let wb = self.write_tag_then_len(Tag::Struct, len)?;
-        Ok(SeqSerializer::new(self, wb, true))
+        let mut wb = self.write_type_name(name)?;
+        wb += self.write_tag_then_len(Tag::Struct, len)?;
+        Ok(SeqSerializer::sized(self, wb, len, true))
     }
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
-        _len: usize,
+        variant: &'static str,
+        len: usize,
     ) -> Result<Self::SerializeStructVariant, W::Error> {
-        let wb = self.write_tag_then_variant(Tag::StructVariant, variant_index)?;
-        Ok(SeqSerializer::new(self, wb, true))
+        let mut wb =
+            self.write_tag_then_variant(Tag::StructVariant, name, variant_index, variant)?;
+        wb += len.serialize(&mut *self)?;
+        Ok(SeqSerializer::sized(self, wb, len, true))
     }
 
     fn is_human_readable(&self) -> bool {
         false
     }
 
-    fn collect_str<T: ?Sized>(self, value: &T) -> Result<Self::Ok, W::Error>
+    fn collect_str<T>(self, value: &T) -> Result<Self::Ok, W::Error>
     where
-        T: fmt::Display,
+        T: ?Sized + fmt::Display,
     {
         // just bring the trait in scope but naming don't matter
         use ser::Error as _;
+        #[cfg(feature = "alloc")]
+        if self.canonical {
+            let mut collected = String::new();
+            fmt::write(&mut collected, format_args!("{}", value))
+                .map_err(|_| Error::custom("Something went really wrong."))?;
+            return self.serialize_str(&collected);
+        }
         // unknown str length marker
         let mut wb = self.write_tag(Tag::MarkerTerminatedString)?;
         let mut collector = StrCollector::new(&mut self.writer);
@@ -354,6 +637,22 @@ pub struct SeqSerializer<'a, W> {
     serializer: &'a mut Serializer<W>,
     written_bytes_count: usize,
     known_size: bool,
+    // number of values the written len announces, a map entry being two values
+    declared_values: Option<usize>,
+    values: usize,
+    entries: bool,
+    streamed_bytes: bool,
+    #[cfg(feature = "alloc")]
+    buffer: Option<Buffer>,
+}
+
+/// Elements buffered in canonical mode, written once complete.
+#[cfg(feature = "alloc")]
+enum Buffer {
+    /// Number of elements and their encoding.
+    Seq(usize, Vec<u8>),
+    /// Key waiting for its value and encoded entries.
+    Map(Option<Vec<u8>>, Vec<(Vec<u8>, Vec<u8>)>),
 }
 
 impl<'a, W: Write> SeqSerializer<'a, W> {
@@ -362,18 +661,104 @@ impl<'a, W: Write> SeqSerializer<'a, W> {
             serializer,
             written_bytes_count: written_bytes,
             known_size,
+            declared_values: None,
+            values: 0,
+            entries: false,
+            streamed_bytes: false,
+            #[cfg(feature = "alloc")]
+            buffer: None,
         }
     }
 
-    pub fn ser_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    /// Serializer of `len` elements, or entries when `entries` is set, whose len is already written.
+    fn sized(
+        serializer: &'a mut Serializer<W>,
+        written_bytes: usize,
+        len: usize,
+        entries: bool,
+    ) -> Self {
+        let mut seq_serializer = SeqSerializer::new(serializer, written_bytes, true);
+        seq_serializer.declared_values = Some(if entries { len.saturating_mul(2) } else { len });
+        seq_serializer.entries = entries;
+        seq_serializer
+    }
+
+    fn len_mismatch(&self, written_values: usize) -> Error<W::Error> {
+        let per_element = if self.entries { 2 } else { 1 };
+        Error::LenMismatch {
+            declared: self.declared_values.unwrap_or_default() / per_element,
+            written: written_values.div_ceil(per_element),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn buffered(serializer: &'a mut Serializer<W>, buffer: Buffer) -> Self {
+        let mut seq_serializer = SeqSerializer::new(serializer, 0, true);
+        seq_serializer.buffer = Some(buffer);
+        seq_serializer
+    }
+
+    pub fn ser_value<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        #[cfg(feature = "alloc")]
+        if self.buffer.is_some() {
+            let bytes = self.serializer.serialize_to_buffer(value)?;
+            match self.buffer.as_mut() {
+                Some(Buffer::Seq(len, buffer)) => {
+                    *len += 1;
+                    buffer.extend_from_slice(&bytes);
+                }
+                Some(Buffer::Map(key, entries)) => match key.take() {
+                    Some(key) => entries.push((key, bytes)),
+                    None => *key = Some(bytes),
+                },
+                None => unreachable!(),
+            }
+            return Ok(());
+        }
+        // the value is not written, so the data stays readable up to it.
+        if self.declared_values == Some(self.values) {
+            return Err(self.len_mismatch(self.values + 1));
+        }
+        self.values += 1;
         self.written_bytes_count += value.serialize(&mut *self.serializer)?;
+        // every element after the length of a streamed byte array is a raw chunk
+        self.serializer.raw_bytes = self.streamed_bytes;
         Ok(())
     }
 
     pub fn finish(mut self) -> Result<usize, W::Error> {
+        #[cfg(feature = "alloc")]
+        match self.buffer.take() {
+            Some(Buffer::Seq(len, buffer)) => {
+                let wb = self.serializer.write_tag_then_len(Tag::Seq, len)?;
+                return Ok(wb + self.serializer.write_bytes(&buffer)?);
+            }
+            Some(Buffer::Map(_, mut entries)) => {
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                    return Err(Error::DuplicateKey);
+                }
+                let mut wb = self
+                    .serializer
+                    .write_tag_then_len(Tag::Map, entries.len())?;
+                for (key, value) in entries {
+                    wb += self.serializer.write_bytes(&key)?;
+                    wb += self.serializer.write_bytes(&value)?;
+                }
+                return Ok(wb);
+            }
+            None => {}
+        }
+        self.serializer.raw_bytes = false;
+        if self
+            .declared_values
+            .is_some_and(|declared| declared != self.values)
+        {
+            return Err(self.len_mismatch(self.values));
+        }
         if !self.known_size {
             self.written_bytes_count += self.serializer.write_tag(Tag::UnsizedSeqEnd)?;
         }
@@ -386,9 +771,9 @@ impl<'a, W: Write> ser::SerializeSeq for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -403,9 +788,9 @@ impl<'a, W: Write> ser::SerializeTuple for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -420,9 +805,9 @@ impl<'a, W: Write> ser::SerializeTupleStruct for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -437,9 +822,9 @@ impl<'a, W: Write> ser::SerializeTupleVariant for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -454,16 +839,16 @@ impl<'a, W: Write> ser::SerializeMap for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), W::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(key)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), W::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.ser_value(value)
     }
@@ -478,9 +863,9 @@ impl<'a, W: Write> ser::SerializeStruct for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         use ser::SerializeMap;
         self.serialize_entry(key, value)
@@ -496,9 +881,9 @@ impl<'a, W: Write> ser::SerializeStructVariant for SeqSerializer<'a, W> {
 
     type Error = Error<W::Error>;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), W::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         use ser::SerializeMap;
         self.serialize_entry(key, value)
@@ -509,6 +894,47 @@ impl<'a, W: Write> ser::SerializeStructVariant for SeqSerializer<'a, W> {
     }
 }
 
+pub struct ChunkedSerializer<'a, W> {
+    serializer: &'a mut Serializer<W>,
+    written_bytes_count: usize,
+    // in canonical mode chunks are joined and written with this tag
+    #[cfg(feature = "alloc")]
+    buffer: Option<(Tag, Vec<u8>)>,
+}
+
+impl<'a, W: Write> ChunkedSerializer<'a, W> {
+    fn new(serializer: &'a mut Serializer<W>, written_bytes: usize) -> Self {
+        ChunkedSerializer {
+            serializer,
+            written_bytes_count: written_bytes,
+            #[cfg(feature = "alloc")]
+            buffer: None,
+        }
+    }
+
+    /// Write a length prefixed chunk, empty chunks are skipped.
+    pub fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), W::Error> {
+        #[cfg(feature = "alloc")]
+        if let Some((_, buffer)) = self.buffer.as_mut() {
+            buffer.extend_from_slice(chunk);
+            return Ok(());
+        }
+        if !chunk.is_empty() {
+            self.written_bytes_count += chunk.len().serialize(&mut *self.serializer)?;
+            self.written_bytes_count += self.serializer.write_bytes(chunk)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<usize, W::Error> {
+        #[cfg(feature = "alloc")]
+        if let Some((tag, buffer)) = self.buffer {
+            return self.serializer.write_tag_then_seq(tag, &buffer);
+        }
+        Ok(self.written_bytes_count + self.serializer.write_tag(Tag::UnsizedSeqEnd)?)
+    }
+}
+
 struct StrCollector<'a, W: Write> {
     writer: &'a mut W,
     written_bytes: usize,
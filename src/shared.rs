@@ -0,0 +1,325 @@
+//! Shared pointers keeping their identity, so shared values are written once and cycles end.
+//!
+//! A `Shared` pointer is serialized as a newtype variant of the enum `SHARED`: `New` with the pointed value the
+//! first time the pointer is met, then `Ref` with the id of the pointer, ids counting the pointers in the order
+//! they are met. Ids are tracked by a context set up by `scope` for the current thread, which `to_bytes` and
+//! `from_bytes` use.
+//!
+//! A pointer met again while its value is serialized, like in a cycle through a `RefCell`, is written as a `Ref`,
+//! but can't be deserialized since its value doesn't exist yet.
+//!
+//! Canonical encoding reorders map entries after their ids are given, so it rejects shared pointers.
+
+use alloc::{boxed::Box, collections::BTreeMap, rc::Rc, sync::Arc, vec::Vec};
+use core::any::Any;
+use core::cell::RefCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use serde::{
+    de::{self, EnumAccess, Unexpected, VariantAccess},
+    ser, Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::io;
+
+use crate::error::EndOfBuff;
+
+/// Name of the enum shared pointers are serialized as a variant of.
+pub const SHARED: &str = "$rsbin::Shared";
+
+const VARIANTS: &[&str] = &["New", "Ref"];
+
+/// Ids of the pointers met so far.
+#[derive(Default)]
+struct Context {
+    /// Serialized pointers by address.
+    ids: BTreeMap<usize, u64>,
+    /// Deserialized pointers by id, `None` while the value is deserialized.
+    pointers: Vec<Option<Box<dyn Any>>>,
+}
+
+std::thread_local! {
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+/// Restore the context of an outer scope.
+struct ScopeGuard(Option<Context>);
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        CONTEXT.with(|context| *context.borrow_mut() = outer);
+    }
+}
+
+/// Run `f` with a new context tracking shared pointers, needed to serialize or deserialize them.
+///
+/// A value must be deserialized in a single scope, as it was serialized.
+pub fn scope<R, F: FnOnce() -> R>(f: F) -> R {
+    let outer = CONTEXT.with(|context| context.replace(Some(Context::default())));
+    let _guard = ScopeGuard(outer);
+    f()
+}
+
+pub fn to_bytes<T>(value: &T) -> crate::ser::Result<Vec<u8>, io::Error>
+where
+    T: ?Sized + Serialize,
+{
+    scope(|| crate::to_bytes(value))
+}
+
+pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> crate::de::Result<T, EndOfBuff>
+where
+    T: Deserialize<'de>,
+{
+    scope(|| crate::from_bytes(bytes))
+}
+
+fn with_context<R, F: FnOnce(&mut Context) -> R>(f: F) -> Option<R> {
+    CONTEXT.with(|context| context.borrow_mut().as_mut().map(f))
+}
+
+const NO_SCOPE: &str =
+    "Shared pointers must be serialized and deserialized in rsbin::shared::scope.";
+
+const CYCLIC: &str = "A cyclic shared pointer can't be deserialized.";
+
+pub(crate) const CANONICAL: &str = "Shared pointers can't be encoded canonically.";
+
+/// `Rc` or `Arc` serialized once however many times it is met.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Shared<P>(pub P);
+
+impl<P> Shared<P> {
+    pub fn into_inner(self) -> P {
+        self.0
+    }
+}
+
+impl<P> Deref for Shared<P> {
+    type Target = P;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<P> From<P> for Shared<P> {
+    fn from(value: P) -> Self {
+        Shared(value)
+    }
+}
+
+fn serialize_pointer<T, S>(address: usize, value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: ?Sized + Serialize,
+    S: Serializer,
+{
+    let id = with_context(|context| {
+        let next_id = context.ids.len() as u64;
+        match context.ids.insert(address, next_id) {
+            Some(id) => {
+                context.ids.insert(address, id);
+                Some(id)
+            }
+            None => None,
+        }
+    })
+    .ok_or_else(|| <S::Error as ser::Error>::custom(NO_SCOPE))?;
+    match id {
+        Some(id) => serializer.serialize_newtype_variant(SHARED, 1, VARIANTS[1], &id),
+        None => serializer.serialize_newtype_variant(SHARED, 0, VARIANTS[0], value),
+    }
+}
+
+enum Variant {
+    New,
+    Ref,
+}
+
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_identifier(VariantVisitor)
+    }
+}
+
+struct VariantVisitor;
+
+impl de::Visitor<'_> for VariantVisitor {
+    type Value = Variant;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("New or Ref")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        match v {
+            0 => Ok(Variant::New),
+            1 => Ok(Variant::Ref),
+            _ => Err(E::invalid_value(Unexpected::Unsigned(v), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        match v {
+            "New" => Ok(Variant::New),
+            "Ref" => Ok(Variant::Ref),
+            _ => Err(E::unknown_variant(v, VARIANTS)),
+        }
+    }
+}
+
+struct SharedVisitor<P>(PhantomData<P>);
+
+macro_rules! implement_shared {
+    ($pointer:ident) => {
+        impl<T: ?Sized + Serialize> Serialize for Shared<$pointer<T>> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let address = $pointer::as_ptr(&self.0) as *const () as usize;
+                serialize_pointer(address, &*self.0, serializer)
+            }
+        }
+
+        impl<'de, T: Deserialize<'de> + 'static> Deserialize<'de> for Shared<$pointer<T>> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer
+                    .deserialize_enum(SHARED, VARIANTS, SharedVisitor::<$pointer<T>>(PhantomData))
+                    .map(Shared)
+            }
+        }
+
+        impl<'de, T: Deserialize<'de> + 'static> de::Visitor<'de> for SharedVisitor<$pointer<T>> {
+            type Value = $pointer<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a shared pointer")
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+                let no_scope = || <A::Error as de::Error>::custom(NO_SCOPE);
+                match data.variant()? {
+                    (Variant::New, variant) => {
+                        let id = with_context(|context| {
+                            context.pointers.push(None);
+                            context.pointers.len() - 1
+                        })
+                        .ok_or_else(no_scope)?;
+                        let pointer = $pointer::new(variant.newtype_variant::<T>()?);
+                        let stored = Box::new(pointer.clone());
+                        with_context(|context| context.pointers[id] = Some(stored))
+                            .ok_or_else(no_scope)?;
+                        Ok(pointer)
+                    }
+                    (Variant::Ref, variant) => {
+                        let id: u64 = variant.newtype_variant()?;
+                        let pointer = with_context(|context| {
+                            let stored = usize::try_from(id)
+                                .ok()
+                                .and_then(|id| context.pointers.get(id))
+                                .ok_or(de::Error::invalid_value(
+                                    Unexpected::Unsigned(id),
+                                    &"the id of a shared pointer",
+                                ))?
+                                .as_ref()
+                                .ok_or(de::Error::custom(CYCLIC))?;
+                            stored
+                                .downcast_ref::<$pointer<T>>()
+                                .cloned()
+                                .ok_or(de::Error::custom(
+                                    "A shared pointer refers to a value of another type.",
+                                ))
+                        })
+                        .ok_or_else(no_scope)?;
+                        pointer
+                    }
+                }
+            }
+        }
+    };
+}
+
+implement_shared!(Rc);
+implement_shared!(Arc);
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Node {
+        name: String,
+        children: Vec<Shared<Rc<Node>>>,
+    }
+
+    #[test]
+    fn test_shared() {
+        let texture = Shared(Rc::new(Node {
+            name: "texture".into(),
+            children: vec![],
+        }));
+        let left = Shared(Rc::new(Node {
+            name: "left".into(),
+            children: vec![texture.clone()],
+        }));
+        let right = Shared(Rc::new(Node {
+            name: "right".into(),
+            children: vec![texture.clone()],
+        }));
+        let scene = Shared(Rc::new(Node {
+            name: "scene".into(),
+            children: vec![left, right],
+        }));
+
+        let bytes = to_bytes(&scene).unwrap();
+        assert_eq!(
+            bytes
+                .windows(7)
+                .filter(|window| window == b"texture")
+                .count(),
+            1
+        );
+        let read: Shared<Rc<Node>> = from_bytes(&bytes).unwrap();
+        let (left, right) = (&read.children[0], &read.children[1]);
+        assert_eq!(left.children[0].name, "texture");
+        assert!(Rc::ptr_eq(&left.children[0], &right.children[0]));
+
+        assert!(crate::to_bytes(&scene).is_err());
+
+        let shared = Shared(Arc::new(String::from("shared")));
+        let bytes = to_bytes(&(shared.clone(), shared)).unwrap();
+        let (first, second): (Shared<Arc<String>>, Shared<Arc<String>>) =
+            from_bytes(&bytes).unwrap();
+        assert_eq!(first.as_str(), "shared");
+        assert!(Arc::ptr_eq(&first, &second));
+
+        #[derive(Serialize)]
+        struct Cyclic {
+            next: Option<Shared<Rc<RefCell<Cyclic>>>>,
+        }
+        let cyclic = Shared(Rc::new(RefCell::new(Cyclic { next: None })));
+        cyclic.borrow_mut().next = Some(cyclic.clone());
+        let bytes = to_bytes(&cyclic).unwrap();
+        cyclic.borrow_mut().next = None;
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct CyclicOwned {
+            next: Option<Shared<Rc<CyclicOwned>>>,
+        }
+        let err = from_bytes::<Shared<Rc<CyclicOwned>>>(&bytes).unwrap_err();
+        assert_eq!(err, crate::DeError::Custom(CYCLIC.into()));
+
+        // sorting the entries would put the `Ref` before its `New`.
+        struct Entries(Shared<Rc<String>>);
+        impl Serialize for Entries {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map([(2, self.0.clone()), (1, self.0.clone())])
+            }
+        }
+        let entries = Entries(Shared(Rc::new(String::from("shared"))));
+        assert!(scope(|| crate::to_bytes(&entries)).is_ok());
+        let err = scope(|| crate::to_canonical_bytes(&entries)).unwrap_err();
+        assert!(matches!(err, crate::SerError::Custom(message) if message == CANONICAL));
+    }
+}
@@ -0,0 +1,187 @@
+//! Authenticated, but not encrypted, rsbin data with HMAC-SHA256.
+//!
+//! Signed data is the id of the key, the encoding of the value and the MAC of both:
+//!
+//! | key id: u32 BE | payload | tag: 32 bytes |
+//!
+//! Values are only deserialized once the tag is verified, and can borrow from the signed data.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io;
+
+use crate::{de, error::EndOfBuff, ser};
+
+const KEY_ID_LEN: usize = 4;
+const TAG_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret key and the id written in the data it signs, so keys can be rotated.
+#[derive(Clone)]
+pub struct Key {
+    id: u32,
+    secret: Vec<u8>,
+}
+
+impl Key {
+    pub fn new(id: u32, secret: &[u8]) -> Self {
+        Key {
+            id,
+            secret: secret.to_vec(),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        // HMAC accepts keys of any len.
+        HmacSha256::new_from_slice(&self.secret).unwrap()
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Serialize(ser::Error<io::Error>),
+    Deserialize(de::Error<EndOfBuff>),
+    /// The data is too short to hold a key id and a tag.
+    InvalidLength(usize),
+    /// None of the keys has the id of the key the data was signed with.
+    UnknownKey(u32),
+    /// The data was modified or signed with another key of the same id.
+    InvalidSignature,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serialize(err) => Display::fmt(err, f),
+            Error::Deserialize(err) => Display::fmt(err, f),
+            Error::InvalidLength(len) => f.write_fmt(format_args!(
+                "Signed data must be at least {} bytes long, got {}.",
+                KEY_ID_LEN + TAG_LEN,
+                len
+            )),
+            Error::UnknownKey(id) => f.write_fmt(format_args!("No key with the id {}.", id)),
+            Error::InvalidSignature => f.write_str("Invalid signature."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ser::Error<io::Error>> for Error {
+    fn from(value: ser::Error<io::Error>) -> Self {
+        Error::Serialize(value)
+    }
+}
+
+impl From<de::Error<EndOfBuff>> for Error {
+    fn from(value: de::Error<EndOfBuff>) -> Self {
+        Error::Deserialize(value)
+    }
+}
+
+/// Serialize `value` and sign it with `key`.
+pub fn to_bytes<T>(value: &T, key: &Key) -> Result<Vec<u8>, Error>
+where
+    T: ?Sized + Serialize,
+{
+    let mut signed = key.id.to_be_bytes().to_vec();
+    crate::to_writer(value, &mut signed)?;
+    let tag = key.mac().chain_update(&signed).finalize().into_bytes();
+    signed.extend_from_slice(&tag);
+    Ok(signed)
+}
+
+/// Id of the key `signed` was signed with.
+pub fn key_id(signed: &[u8]) -> Result<u32, Error> {
+    if signed.len() < KEY_ID_LEN + TAG_LEN {
+        return Err(Error::InvalidLength(signed.len()));
+    }
+    Ok(u32::from_be_bytes(signed[..KEY_ID_LEN].try_into().unwrap()))
+}
+
+/// Verify `signed` with the key of `keys` that has its id, giving back the encoded value.
+pub fn verify<'a>(signed: &'a [u8], keys: &[Key]) -> Result<&'a [u8], Error> {
+    let id = key_id(signed)?;
+    let key = keys
+        .iter()
+        .find(|key| key.id == id)
+        .ok_or(Error::UnknownKey(id))?;
+    let (data, tag) = signed.split_at(signed.len() - TAG_LEN);
+    key.mac()
+        .chain_update(data)
+        .verify_slice(tag)
+        .map_err(|_| Error::InvalidSignature)?;
+    Ok(&data[KEY_ID_LEN..])
+}
+
+/// Verify then deserialize `signed`, see `verify`.
+pub fn from_bytes<'a, T: Deserialize<'a>>(signed: &'a [u8], keys: &[Key]) -> Result<T, Error> {
+    Ok(crate::from_bytes(verify(signed, keys)?)?)
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Session<'a> {
+        user: &'a str,
+        expires: u64,
+    }
+
+    #[test]
+    fn test_signed() {
+        let session = Session {
+            user: "ada",
+            expires: 1_700_000_000,
+        };
+        let old = Key::new(1, b"old secret");
+        let current = Key::new(2, b"current secret");
+        let keys = [old.clone(), current.clone()];
+
+        let token = to_bytes(&session, &current).unwrap();
+        assert_eq!(from_bytes::<Session>(&token, &keys).unwrap(), session);
+        let signed_with_old = to_bytes(&session, &old).unwrap();
+        assert_eq!(key_id(&signed_with_old).unwrap(), 1);
+        assert_eq!(
+            from_bytes::<Session>(&signed_with_old, &keys).unwrap(),
+            session
+        );
+
+        let mut forged = token.clone();
+        // `user` becomes "adb"
+        let position = forged
+            .windows(3)
+            .position(|window| window == b"ada")
+            .unwrap();
+        forged[position + 2] = b'b';
+        assert!(matches!(
+            from_bytes::<Session>(&forged, &keys),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            from_bytes::<Session>(&token, &[old]),
+            Err(Error::UnknownKey(2))
+        ));
+        assert!(matches!(
+            from_bytes::<Session>(&token[..20], &keys),
+            Err(Error::InvalidLength(20))
+        ));
+    }
+}
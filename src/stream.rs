@@ -0,0 +1,332 @@
+//! Streaming of large byte arrays between `std::io` and rsbin, without holding them in memory.
+//!
+//! On the wire a streamed byte array is a regular byte array, so it can be read back as any bytes
+//! and any byte array can be read with a `BytesSink`.
+
+#[cfg(feature = "std")]
+use core::{
+    cell::{Cell, RefCell},
+    fmt,
+};
+#[cfg(feature = "std")]
+use serde::{de, ser, Serialize};
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(feature = "std")]
+use crate::{de::Deserializer, read::Read};
+
+/// Newtype name used to recognize streamed byte arrays in the `Serializer` and the `Deserializer`.
+pub(crate) const STREAMED_BYTES: &str = "$rsbin::private::StreamedBytes";
+
+/// Size of the chunks read from or handed to the io.
+pub(crate) const STREAM_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Byte array of a known length serialized straight from an `io::Read`.
+///
+/// The reader must yield at least `len` bytes, only the first `len` are written.
+/// Other serializers see it as a tuple of the length followed by byte array chunks.
+///
+/// It can be serialized only once, as serializing it consumes the reader: serializing it again fails,
+/// so it can't be sized by `get_serialized_size` before being written.
+#[cfg(feature = "std")]
+pub struct StreamedBytes<R> {
+    reader: RefCell<R>,
+    len: u64,
+    serialized: Cell<bool>,
+}
+
+#[cfg(feature = "std")]
+const SERIALIZED: &str = "Streamed bytes can be serialized only once, their reader is consumed.";
+
+#[cfg(feature = "std")]
+impl<R: io::Read> StreamedBytes<R> {
+    pub fn new(reader: R, len: u64) -> Self {
+        StreamedBytes {
+            reader: RefCell::new(reader),
+            len,
+            serialized: Cell::new(false),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> Serialize for StreamedBytes<R> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(STREAMED_BYTES, &StreamedChunks(self))
+    }
+}
+
+#[cfg(feature = "std")]
+struct StreamedChunks<'a, R>(&'a StreamedBytes<R>);
+
+#[cfg(feature = "std")]
+impl<R: io::Read> Serialize for StreamedChunks<'_, R> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use ser::{Error, SerializeTuple};
+        if self.0.serialized.replace(true) {
+            return Err(S::Error::custom(SERIALIZED));
+        }
+        let len = self.0.len;
+        let chunks_count = usize::try_from(len.div_ceil(STREAM_CHUNK_SIZE as u64))
+            .map_err(|_| S::Error::custom("Streamed bytes are too long for this platform."))?;
+        let mut tuple = serializer.serialize_tuple(chunks_count + 1)?;
+        tuple.serialize_element(&len)?;
+        let mut reader = self.0.reader.borrow_mut();
+        let mut buff = [0; STREAM_CHUNK_SIZE];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk_len = remaining.min(STREAM_CHUNK_SIZE as u64) as usize;
+            let chunk = &mut buff[..chunk_len];
+            reader.read_exact(chunk).map_err(S::Error::custom)?;
+            tuple.serialize_element(&Chunk(chunk))?;
+            remaining -= chunk_len as u64;
+        }
+        tuple.end()
+    }
+}
+
+#[cfg(feature = "std")]
+struct Chunk<'a>(&'a [u8]);
+
+#[cfg(feature = "std")]
+impl Serialize for Chunk<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// `DeserializeSeed` copying a byte array into an `io::Write` chunk by chunk.
+///
+/// Its value is the number of bytes written.
+#[cfg(feature = "std")]
+pub struct BytesSink<'w, W> {
+    writer: &'w mut W,
+}
+
+#[cfg(feature = "std")]
+impl<'w, W: io::Write> BytesSink<'w, W> {
+    pub fn new(writer: &'w mut W) -> Self {
+        BytesSink { writer }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, W: io::Write> de::DeserializeSeed<'de> for BytesSink<'_, W> {
+    type Value = u64;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(STREAMED_BYTES, self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, W: io::Write> de::Visitor<'de> for BytesSink<'_, W> {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte array")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        use de::Error;
+        let len = seq
+            .next_element_seed(LenSeed)?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let mut written = 0;
+        while let Some(chunk_len) = seq.next_element_seed(ChunkSink(&mut *self.writer))? {
+            written += chunk_len;
+        }
+        match len {
+            Some(len) if written != len => Err(A::Error::custom(format_args!(
+                "Streamed bytes announced {} bytes but contained {}.",
+                len, written
+            ))),
+            _ => Ok(written),
+        }
+    }
+}
+
+/// Length of streamed bytes, a unit for chunked bytes whose length isn't known upfront.
+#[cfg(feature = "std")]
+struct LenSeed;
+
+#[cfg(feature = "std")]
+impl<'de> de::DeserializeSeed<'de> for LenSeed {
+    type Value = Option<u64>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_u64(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de> de::Visitor<'de> for LenSeed {
+    type Value = Option<u64>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the length of a byte array")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Some(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "std")]
+struct ChunkSink<'a, W>(&'a mut W);
+
+#[cfg(feature = "std")]
+impl<'de, W: io::Write> de::DeserializeSeed<'de> for ChunkSink<'_, W> {
+    type Value = u64;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, W: io::Write> de::Visitor<'de> for ChunkSink<'_, W> {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a chunk of bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.0.write_all(v).map_err(E::custom)?;
+        Ok(v.len() as u64)
+    }
+}
+
+/// Copy a byte array read from `reader` into `writer` without holding it in memory.
+///
+/// Return the number of bytes copied.
+#[cfg(feature = "std")]
+pub fn copy_bytes<'de, R, W>(reader: R, writer: &mut W) -> crate::de::Result<u64, R::Error>
+where
+    R: Read<'de>,
+    W: io::Write,
+{
+    let mut de = Deserializer::new(reader);
+    de::DeserializeSeed::deserialize(BytesSink::new(writer), &mut de)
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streamed_bytes_round_trip() {
+        let payload: Vec<u8> = (0..3 * STREAM_CHUNK_SIZE + 17).map(|i| i as u8).collect();
+        let value = StreamedBytes::new(&payload[..], payload.len() as u64);
+        let bytes = crate::to_bytes(&value).unwrap();
+        // same encoding as a regular byte array
+        let expected = crate::to_bytes(serde_bytes::Bytes::new(&payload)).unwrap();
+        assert_eq!(bytes, expected);
+
+        let mut sink = Vec::new();
+        let io_reader = crate::read::IoReader::new(&bytes[..]);
+        let copied = copy_bytes(io_reader, &mut sink).unwrap();
+        assert_eq!(copied, payload.len() as u64);
+        assert_eq!(sink, payload);
+    }
+
+    #[test]
+    fn test_streamed_bytes_in_record() {
+        let payload = b"attachment";
+        let value = ("header", StreamedBytes::new(&payload[..], 10));
+        let bytes = crate::to_bytes(&value).unwrap();
+        let (header, attachment): (&str, &serde_bytes::Bytes) = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(header, "header");
+        assert_eq!(attachment.as_ref(), payload);
+    }
+
+    #[test]
+    fn test_streamed_chunked_bytes() {
+        use serde::Serializer as _;
+        let mut bytes = Vec::new();
+        let mut serializer = crate::Serializer::new(&mut bytes);
+        let mut chunked = serializer.serialize_chunked_bytes().unwrap();
+        chunked.write_chunk(b"chunked ").unwrap();
+        chunked.write_chunk(b"").unwrap();
+        chunked.write_chunk(b"bytes").unwrap();
+        chunked.finish().unwrap();
+        serializer.serialize_u8(7).unwrap();
+
+        let mut sink = Vec::new();
+        let mut reader = &bytes[..];
+        assert_eq!(copy_bytes(&mut reader, &mut sink).unwrap(), 13);
+        assert_eq!(sink, b"chunked bytes");
+        assert_eq!(crate::from_reader::<u8, _>(reader).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_streamed_bytes_too_short() {
+        let payload = [1, 2, 3];
+        let value = StreamedBytes::new(&payload[..], 4);
+        assert!(crate::to_bytes(&value).is_err());
+    }
+
+    #[test]
+    fn test_streamed_bytes_serialized_once() {
+        let payload = [1, 2, 3];
+        let value = StreamedBytes::new(&payload[..], 3);
+        assert_eq!(crate::get_serialized_size(&value).unwrap(), 6);
+        let err = crate::to_bytes(&value).unwrap_err();
+        assert!(matches!(err, crate::SerError::Custom(message) if message == SERIALIZED));
+    }
+}
@@ -2,10 +2,6 @@ use core::fmt::Display;
 
 pub const UNSIZED_STRING_END_MARKER: [u8; 2] = [0xD8, 0x00];
 
-pub fn end_of_str(bytes: &[u8; 2]) -> bool {
-    bytes == &UNSIZED_STRING_END_MARKER
-}
-
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 #[repr(u8)]
 pub enum Tag {
@@ -49,6 +45,11 @@ pub enum Tag {
     I128 = 36,
     #[cfg(not(no_integer128))]
     U128 = 37,
+    ChunkedString = 38,
+    ChunkedBytes = 39,
+    /// Prefix of a struct, newtype or enum tag, followed by the name of the type or the index of an already
+    /// written name.
+    TypeName = 40,
 }
 
 impl Tag {
@@ -147,6 +148,9 @@ impl TryFrom<u8> for Tag {
             37 => Ok(Tag::U128),
             #[cfg(no_integer128)]
             37 | 36 => Err(TagParsingError::Integer128),
+            38 => Ok(Tag::ChunkedString),
+            39 => Ok(Tag::ChunkedBytes),
+            40 => Ok(Tag::TypeName),
             tag => Err(TagParsingError::InvalidTag(tag)),
         }
     }
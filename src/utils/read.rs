@@ -1,8 +1,55 @@
-#[cfg(feature = "alloc")]
-use alloc::borrow::Cow;
+use core::ops::Deref;
+use core::str::Utf8Error;
 
 use crate::error::{EndOfBuff, RWError};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Bytes handed out by a reader, either borrowed from the input for the whole `'de` lifetime
+/// or copied into a scratch buffer owned by the reader and only valid until the next read.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reference<'de, 'a, T: ?Sized> {
+    Borrowed(&'de T),
+    Copied(&'a T),
+}
+
+impl<'de, 'a, T: ?Sized> Reference<'de, 'a, T> {
+    pub fn map<U: ?Sized, F>(self, f: F) -> Reference<'de, 'a, U>
+    where
+        F: for<'x> FnOnce(&'x T) -> &'x U,
+    {
+        match self {
+            Reference::Borrowed(value) => Reference::Borrowed(f(value)),
+            Reference::Copied(value) => Reference::Copied(f(value)),
+        }
+    }
+}
+
+impl<'de, 'a> Reference<'de, 'a, [u8]> {
+    pub fn to_str(self) -> Result<Reference<'de, 'a, str>, Utf8Error> {
+        match self {
+            Reference::Borrowed(bytes) => core::str::from_utf8(bytes).map(Reference::Borrowed),
+            Reference::Copied(bytes) => core::str::from_utf8(bytes).map(Reference::Copied),
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for Reference<'_, '_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Reference::Borrowed(value) => value,
+            Reference::Copied(value) => value,
+        }
+    }
+}
 
+/// Source of the bytes a `Deserializer` reads.
+///
+/// It is implemented for byte slices, `BuffReader` and, with `std`, `IoReader` over a `BufRead`. It isn't
+/// implemented for every `std::io::Read` anymore: use `from_io_reader` for them, or wrap a `BufRead` in an
+/// `IoReader` to pass to `from_reader`.
 pub trait Read<'de> {
     type Error: RWError;
 
@@ -14,25 +61,110 @@ pub trait Read<'de> {
         Ok(byte)
     }
 
+    fn read_bytes<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a, [u8]>, Self::Error>;
+
+    /// The result should end with the 2 bytes of `marker`, and they should be the first occurence of it.
+    fn read_bytes_until<'a>(
+        &'a mut self,
+        marker: &[u8; 2],
+    ) -> Result<Reference<'de, 'a, [u8]>, Self::Error>;
+
+    /// Whether every byte was read, for `Deserializer::end`.
+    fn is_at_end(&mut self) -> Result<bool, Self::Error>;
+
+    /// Start copying the bytes read, giving back where they start in the copy, so a strict `Deserializer`
+    /// can check the order of map keys. Only a `Recorder` copies them, other readers give back `None`.
     #[cfg(feature = "alloc")]
-    fn read_bytes(&mut self, len: usize) -> Result<Cow<'de, [u8]>, Self::Error>;
+    fn start_recording(&mut self) -> Option<usize> {
+        None
+    }
+
+    /// The bytes read since `start_recording` gave back `start`, stopping that recording.
+    #[cfg(feature = "alloc")]
+    fn stop_recording(&mut self, start: usize) -> Vec<u8> {
+        let _ = start;
+        Vec::new()
+    }
+}
+
+impl<'de, R: Read<'de> + ?Sized> Read<'de> for &mut R {
+    type Error = R::Error;
+
+    fn read_to_buff(&mut self, buff: &mut [u8]) -> Result<(), Self::Error> {
+        (**self).read_to_buff(buff)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        (**self).read_byte()
+    }
+
+    fn read_bytes<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        (**self).read_bytes(len)
+    }
 
-    #[cfg(not(feature = "alloc"))]
-    fn read_bytes(&mut self, len: usize) -> Result<&'de [u8], Self::Error>;
+    fn read_bytes_until<'a>(
+        &'a mut self,
+        marker: &[u8; 2],
+    ) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        (**self).read_bytes_until(marker)
+    }
+
+    fn is_at_end(&mut self) -> Result<bool, Self::Error> {
+        (**self).is_at_end()
+    }
 
-    /// The result Cow<[u8]> should end with the last 2 bytes passed to the `end_of_bytes` callback.
     #[cfg(feature = "alloc")]
-    fn read_bytes_until(
-        &mut self,
-        end_of_bytes: fn(&[u8; 2]) -> bool,
-    ) -> Result<Cow<'de, [u8]>, Self::Error>;
+    fn start_recording(&mut self) -> Option<usize> {
+        (**self).start_recording()
+    }
+
+    #[cfg(feature = "alloc")]
+    fn stop_recording(&mut self, start: usize) -> Vec<u8> {
+        (**self).stop_recording(start)
+    }
+}
+
+/// Reading straight from a slice, every read borrows from the input.
+impl<'de> Read<'de> for &'de [u8] {
+    type Error = EndOfBuff;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        let (first, rest) = self.split_first().ok_or(EndOfBuff)?;
+        *self = rest;
+        Ok(*first)
+    }
+
+    fn read_to_buff(&mut self, buff: &mut [u8]) -> Result<(), Self::Error> {
+        let to_copy = pop_slice(self, buff.len())?;
+        buff.copy_from_slice(to_copy);
+        Ok(())
+    }
 
-    /// The result Cow<[u8]> should end with the last 2 bytes passed to the `end_of_bytes` callback.
-    #[cfg(not(feature = "alloc"))]
-    fn read_bytes_until(
-        &mut self,
-        end_of_bytes: fn(&[u8; 2]) -> bool,
-    ) -> Result<&'de [u8], Self::Error>;
+    fn read_bytes<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        pop_slice(self, len).map(Reference::Borrowed)
+    }
+
+    fn read_bytes_until<'a>(
+        &'a mut self,
+        marker: &[u8; 2],
+    ) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        let len = memchr::memmem::find(self, marker).ok_or(EndOfBuff)?;
+        pop_slice(self, len + 2).map(Reference::Borrowed)
+    }
+
+    fn is_at_end(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_empty())
+    }
+}
+
+fn pop_slice<'de>(buff: &mut &'de [u8], len: usize) -> Result<&'de [u8], EndOfBuff> {
+    if buff.len() < len {
+        Err(EndOfBuff)
+    } else {
+        let (popped, rest) = buff.split_at(len);
+        *buff = rest;
+        Ok(popped)
+    }
 }
 
 pub struct BuffReader<'de> {
@@ -44,104 +176,230 @@ impl<'de> BuffReader<'de> {
         BuffReader { buff }
     }
 
-    fn pop_slice(&mut self, len: usize) -> Result<&'de [u8], EndOfBuff> {
-        if self.buff.len() < len {
-            Err(EndOfBuff)
-        } else {
-            let (popped, rest) = self.buff.split_at(len);
-            self.buff = rest;
-            Ok(popped)
+    /// Whether every byte was read.
+    pub fn is_empty(&self) -> bool {
+        self.buff.is_empty()
+    }
+}
+
+impl<'de> Read<'de> for BuffReader<'de> {
+    type Error = EndOfBuff;
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        self.buff.read_byte()
+    }
+
+    fn read_to_buff(&mut self, buff: &mut [u8]) -> Result<(), Self::Error> {
+        self.buff.read_to_buff(buff)
+    }
+
+    fn read_bytes<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        self.buff.read_bytes(len)
+    }
+
+    fn read_bytes_until<'a>(
+        &'a mut self,
+        marker: &[u8; 2],
+    ) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        self.buff.read_bytes_until(marker)
+    }
+
+    fn is_at_end(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.is_empty())
+    }
+}
+
+/// Reader copying the bytes read while map keys are read, for a strict `Deserializer` to check their order.
+///
+/// Other readers read without copying anything, so map keys are only checked when reading through a `Recorder`.
+#[cfg(feature = "alloc")]
+pub struct Recorder<R> {
+    reader: R,
+    recording: Vec<u8>,
+    // recordings started and not stopped yet, the keys of maps in a key are recorded with it
+    depth: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<R> Recorder<R> {
+    pub fn new(reader: R) -> Self {
+        Recorder {
+            reader,
+            recording: Vec::new(),
+            depth: 0,
         }
     }
 
-    fn read_until(&mut self, end_of_bytes: fn(&[u8; 2]) -> bool) -> Result<&'de [u8], EndOfBuff> {
-        let len = self
-            .buff
-            .windows(2)
-            .position(|bytes| end_of_bytes(bytes.try_into().unwrap()))
-            .ok_or(EndOfBuff)?;
-        self.pop_slice(len + 2)
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn record(&mut self, bytes: &[u8]) {
+        if self.depth > 0 {
+            self.recording.extend_from_slice(bytes);
+        }
     }
 }
 
-impl<'de> Read<'de> for BuffReader<'de> {
-    type Error = EndOfBuff;
+#[cfg(feature = "alloc")]
+impl<'de, R: Read<'de>> Read<'de> for Recorder<R> {
+    type Error = R::Error;
 
     fn read_byte(&mut self) -> Result<u8, Self::Error> {
-        let (first, rest) = self.buff.split_first().ok_or(EndOfBuff)?;
-        self.buff = rest;
-        Ok(*first)
+        let byte = self.reader.read_byte()?;
+        self.record(&[byte]);
+        Ok(byte)
     }
 
     fn read_to_buff(&mut self, buff: &mut [u8]) -> Result<(), Self::Error> {
-        let to_copy = self.pop_slice(buff.len())?;
-        buff.copy_from_slice(to_copy);
+        self.reader.read_to_buff(buff)?;
+        self.record(buff);
         Ok(())
     }
 
-    #[cfg(feature = "alloc")]
-    fn read_bytes(&mut self, len: usize) -> Result<Cow<'de, [u8]>, Self::Error> {
-        self.pop_slice(len).map(Cow::Borrowed)
+    fn read_bytes<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        let bytes = self.reader.read_bytes(len)?;
+        if self.depth > 0 {
+            self.recording.extend_from_slice(&bytes);
+        }
+        Ok(bytes)
+    }
+
+    fn read_bytes_until<'a>(
+        &'a mut self,
+        marker: &[u8; 2],
+    ) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        let bytes = self.reader.read_bytes_until(marker)?;
+        if self.depth > 0 {
+            self.recording.extend_from_slice(&bytes);
+        }
+        Ok(bytes)
     }
 
-    #[cfg(not(feature = "alloc"))]
-    fn read_bytes(&mut self, len: usize) -> Result<&'de [u8], Self::Error> {
-        self.pop_slice(len)
+    fn is_at_end(&mut self) -> Result<bool, Self::Error> {
+        self.reader.is_at_end()
     }
 
-    #[cfg(feature = "alloc")]
-    fn read_bytes_until(
-        &mut self,
-        end_of_bytes: fn(&[u8; 2]) -> bool,
-    ) -> Result<Cow<'de, [u8]>, Self::Error> {
-        self.read_until(end_of_bytes).map(Cow::Borrowed)
+    fn start_recording(&mut self) -> Option<usize> {
+        self.depth += 1;
+        Some(self.recording.len())
     }
 
-    #[cfg(not(feature = "alloc"))]
-    fn read_bytes_until(
-        &mut self,
-        end_of_bytes: fn(&[u8; 2]) -> bool,
-    ) -> Result<&'de [u8], Self::Error> {
-        self.read_until(end_of_bytes)
+    fn stop_recording(&mut self, start: usize) -> Vec<u8> {
+        self.depth -= 1;
+        let bytes = self.recording[start..].to_vec();
+        if self.depth == 0 {
+            self.recording.clear();
+        }
+        bytes
     }
 }
 
+/// Reader over any `std::io::BufRead`.
+///
+/// Strings and bytes are copied in an internal scratch buffer that is reused between reads,
+/// so they are handed to the visitor without any allocation once the scratch buffer is big enough.
 #[cfg(feature = "std")]
-impl<'de, T: ?Sized> Read<'de> for T
-where
-    T: std::io::Read,
-{
+pub struct IoReader<R> {
+    reader: R,
+    scratch: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::BufRead> IoReader<R> {
+    pub fn new(reader: R) -> Self {
+        IoReader {
+            reader,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, R: std::io::BufRead> Read<'de> for IoReader<R> {
     type Error = std::io::Error;
 
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        let byte = *self
+            .reader
+            .fill_buf()?
+            .first()
+            .ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        self.reader.consume(1);
+        Ok(byte)
+    }
+
     fn read_to_buff(&mut self, buff: &mut [u8]) -> Result<(), Self::Error> {
-        self.read_exact(buff)
-    }
-
-    fn read_bytes(&mut self, len: usize) -> Result<Cow<'de, [u8]>, Self::Error> {
-        let mut buff = vec![0; len];
-        self.read_exact(&mut buff)?;
-        Ok(Cow::Owned(buff))
-    }
-
-    fn read_bytes_until(
-        &mut self,
-        end_of_bytes: fn(&[u8; 2]) -> bool,
-    ) -> Result<Cow<'de, [u8]>, Self::Error> {
-        let mut intermidiate = [0; 2];
-        self.read_exact(&mut intermidiate)?;
-        let mut buff: Vec<u8> = intermidiate.into();
-        while !end_of_bytes(&intermidiate) {
-            intermidiate.swap(0, 1);
-            self.read_exact(&mut intermidiate[1..])?;
-            buff.push(intermidiate[1]);
+        self.reader.read_exact(buff)
+    }
+
+    fn read_bytes<'a>(&'a mut self, len: usize) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        use std::io::Read as _;
+        self.scratch.clear();
+        // the len comes from the data, the scratch buffer only grows with the bytes actually read.
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut self.scratch)?;
+        if self.scratch.len() < len {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Reference::Copied(&self.scratch))
+    }
+
+    fn read_bytes_until<'a>(
+        &'a mut self,
+        marker: &[u8; 2],
+    ) -> Result<Reference<'de, 'a, [u8]>, Self::Error> {
+        self.scratch.clear();
+        loop {
+            let buff = self.reader.fill_buf()?;
+            if buff.is_empty() {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            // the marker can be split between the previous buffer and this one.
+            if self.scratch.last() == Some(&marker[0]) && buff[0] == marker[1] {
+                self.scratch.push(buff[0]);
+                self.reader.consume(1);
+                return Ok(Reference::Copied(&self.scratch));
+            }
+            match memchr::memmem::find(buff, marker) {
+                Some(pos) => {
+                    self.scratch.extend_from_slice(&buff[..pos + 2]);
+                    self.reader.consume(pos + 2);
+                    return Ok(Reference::Copied(&self.scratch));
+                }
+                None => {
+                    let len = buff.len();
+                    self.scratch.extend_from_slice(buff);
+                    self.reader.consume(len);
+                }
+            }
         }
-        Ok(buff.into())
+    }
+    fn is_at_end(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.reader.fill_buf()?.is_empty())
     }
 }
 
 #[cfg(all(test, feature = "test-utils"))]
 mod tests {
-    use crate::tag::{end_of_str, UNSIZED_STRING_END_MARKER};
+    use crate::tag::UNSIZED_STRING_END_MARKER;
 
     use super::*;
 
@@ -150,9 +408,28 @@ mod tests {
         const STRING: &[u8] = b"test_string";
         let mut bytes = STRING.to_vec();
         bytes.extend_from_slice(&UNSIZED_STRING_END_MARKER);
-        let mut bytes_ref: &[u8] = &bytes;
-        let v = bytes_ref.read_bytes_until(end_of_str).unwrap();
-        assert_eq!(v, bytes);
+        let mut io_reader = IoReader::new(&bytes[..]);
+        let v = io_reader
+            .read_bytes_until(&UNSIZED_STRING_END_MARKER)
+            .unwrap();
+        assert_eq!(&*v, &bytes);
+    }
+
+    #[test]
+    fn test_read_until_io_read_split_marker() {
+        const STRING: &[u8] = b"test_string";
+        let mut bytes = STRING.to_vec();
+        bytes.extend_from_slice(&UNSIZED_STRING_END_MARKER);
+        bytes.extend_from_slice(b"rest");
+        // a capacity of 4 split the marker between 2 calls to fill_buf.
+        let buf_reader = std::io::BufReader::with_capacity(4, &bytes[..]);
+        let mut io_reader = IoReader::new(buf_reader);
+        let v = io_reader
+            .read_bytes_until(&UNSIZED_STRING_END_MARKER)
+            .unwrap();
+        assert_eq!(&*v, &bytes[..STRING.len() + 2]);
+        let rest = io_reader.read_bytes(4).unwrap();
+        assert_eq!(&*rest, b"rest");
     }
 
     #[test]
@@ -161,9 +438,18 @@ mod tests {
         const STRING: &[u8] = b"test_string";
         let mut bytes = STRING.to_vec();
         bytes.extend_from_slice(&UNSIZED_STRING_END_MARKER);
-        let mut bytes_ref: &[u8] = &bytes;
-        let v = bytes_ref.read_bytes_until(|_| false).unwrap();
-        assert_eq!(v, bytes);
+        let mut io_reader = IoReader::new(&bytes[..]);
+        let v = io_reader.read_bytes_until(&[0xFF, 0xFF]).unwrap();
+        assert_eq!(&*v, &bytes);
+    }
+
+    #[test]
+    fn test_read_bytes_io_read_len_past_the_end() {
+        let mut io_reader = IoReader::new(&b"0123456789"[..]);
+        let err = io_reader.read_bytes(usize::MAX).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        let mut io_reader = IoReader::new(&b"0123456789"[..]);
+        assert_eq!(&*io_reader.read_bytes(10).unwrap(), b"0123456789");
     }
 
     #[test]
@@ -172,8 +458,10 @@ mod tests {
         let mut bytes = STRING.to_vec();
         bytes.extend_from_slice(&UNSIZED_STRING_END_MARKER);
         let mut buff_reader = BuffReader::new(&bytes);
-        let v = buff_reader.read_bytes_until(end_of_str).unwrap();
-        assert_eq!(v, bytes);
+        let v = buff_reader
+            .read_bytes_until(&UNSIZED_STRING_END_MARKER)
+            .unwrap();
+        assert_eq!(v, Reference::Borrowed(&bytes[..]));
     }
 
     #[test]
@@ -183,7 +471,7 @@ mod tests {
         let mut bytes = STRING.to_vec();
         bytes.extend_from_slice(&UNSIZED_STRING_END_MARKER);
         let mut buff_reader = BuffReader::new(&bytes);
-        let v = buff_reader.read_bytes_until(|_| false).unwrap();
-        assert_eq!(v, bytes);
+        let v = buff_reader.read_bytes_until(&[0xFF, 0xFF]).unwrap();
+        assert_eq!(&*v, &bytes);
     }
 }
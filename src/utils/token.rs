@@ -69,7 +69,7 @@ pub enum Number {
 }
 
 //////////////////////////////////////////////////////////////////////////
-///                    Util functions                                  ///
+//                     Util functions                                   //
 //////////////////////////////////////////////////////////////////////////
 
 impl From<Number> for Token<'_> {
@@ -219,6 +219,7 @@ impl<'a, 'b> PartialEq<Token<'a>> for Token<'b> {
 }
 
 #[derive(Debug)]
+#[allow(unused)]
 pub enum CheckError {
     SerializationError(SerError<std::io::Error>),
     DeserializationError(DeError<EndOfBuff>),
@@ -235,9 +236,9 @@ impl From<DeError<EndOfBuff>> for CheckError {
     }
 }
 
-pub fn check_ser<T: ?Sized>(value: &T, token_tree: &Token) -> Result<(), CheckError>
+pub fn check_ser<T>(value: &T, token_tree: &Token) -> Result<(), CheckError>
 where
-    T: Serialize,
+    T: ?Sized + Serialize,
 {
     let bytes = crate::ser::to_bytes(value)?;
     let tt: Token = crate::de::from_bytes(&bytes)?;
@@ -245,9 +246,9 @@ where
     Ok(())
 }
 
-pub fn check_de<T: ?Sized>(value: &T, token_tree: &Token) -> Result<(), CheckError>
+pub fn check_de<T>(value: &T, token_tree: &Token) -> Result<(), CheckError>
 where
-    T: for<'de> Deserialize<'de> + PartialEq + Debug,
+    T: ?Sized + for<'de> Deserialize<'de> + PartialEq + Debug,
 {
     let bytes = crate::ser::to_bytes(token_tree)?;
     let deserialized_value: T = crate::de::from_bytes(&bytes)?;
@@ -255,9 +256,9 @@ where
     Ok(())
 }
 
-pub fn check_round_trip<T: ?Sized>(value: &T, token_tree: &Token) -> Result<(), CheckError>
+pub fn check_round_trip<T>(value: &T, token_tree: &Token) -> Result<(), CheckError>
 where
-    T: Serialize + for<'de> Deserialize<'de> + PartialEq + Debug,
+    T: ?Sized + Serialize + for<'de> Deserialize<'de> + PartialEq + Debug,
 {
     check_ser(value, token_tree)?;
     check_de(value, token_tree)?;
@@ -286,7 +287,7 @@ macro_rules! token_struct {
 }
 
 //////////////////////////////////////////////////////////////////////////
-///                    Deserialization                                 ///
+//                     Deserialization                                  //
 //////////////////////////////////////////////////////////////////////////
 
 struct TokenVisitor;
@@ -516,7 +517,7 @@ impl<'de> Deserialize<'de> for Token<'de> {
 }
 
 //////////////////////////////////////////////////////////////////////////
-///                    Serialization                                   ///
+//                     Serialization                                    //
 //////////////////////////////////////////////////////////////////////////
 
 impl Serialize for Number {
@@ -21,7 +21,7 @@ pub trait Write {
 }
 
 #[cfg(all(feature = "alloc", not(feature = "std")))]
-impl<'a> Write for &'a mut Vec<u8> {
+impl Write for &mut Vec<u8> {
     type Error = NoRWError;
 
     fn write_byte(&mut self, byte: u8) -> Result<usize, Self::Error> {
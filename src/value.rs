@@ -0,0 +1,614 @@
+//! Values of any type read from the tag stream, without knowing the Rust type they were serialized from.
+//!
+//! Differences that only come from the encoding are normalized away: numbers only keep their signedness,
+//! sized and unsized collections are the same, and strings and byte arrays are read whole whatever the way
+//! they were written.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::fmt;
+use serde::{de, Deserialize, Serialize};
+
+use crate::{
+    de::{Error, Result},
+    error::{EndOfBuff, UnexpectedTag},
+    read::{Read, Reference},
+    tag::{Tag, UNSIZED_STRING_END_MARKER},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Signed(i128),
+    Unsigned(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    Option(Option<Box<Value>>),
+    UnitStruct,
+    NewTypeStruct(Box<Value>),
+    Seq(Vec<Value>),
+    Tuple(Vec<Value>),
+    TupleStruct(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Struct(Vec<(String, Value)>),
+    Variant {
+        /// The variant index, name, or both, as written by the serializer.
+        id: Box<Value>,
+        content: VariantContent,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantContent {
+    Unit,
+    NewType(Box<Value>),
+    Tuple(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Value, EndOfBuff> {
+        Value::parse_bytes(bytes).map(|(value, _)| value)
+    }
+
+    /// Read the value of `bytes`, and whether it has type names, which aren't kept.
+    pub(crate) fn parse_bytes(bytes: &[u8]) -> Result<(Value, bool), EndOfBuff> {
+        let mut parser = Parser {
+            reader: bytes,
+            type_names: false,
+        };
+        let tag = parser.tag()?;
+        let value = parser.value(tag)?;
+        let reader = parser.reader;
+        if !reader.is_empty() {
+            return Err(serde::de::Error::custom(format_args!(
+                "{} trailing bytes after the value",
+                reader.len()
+            )));
+        }
+        Ok((value, parser.type_names))
+    }
+
+    /// Read a single value, the reader is left right after it.
+    pub fn from_reader<'de, R: Read<'de>>(reader: R) -> Result<Value, R::Error> {
+        let mut parser = Parser {
+            reader,
+            type_names: false,
+        };
+        let tag = parser.tag()?;
+        parser.value(tag)
+    }
+
+    /// Append the encoding of the value to `output`.
+    ///
+    /// Numbers use the smallest tag they fit in and collections and strings are written with their length,
+    /// so the encoding is canonical if map entries are sorted.
+    pub fn encode(&self, output: &mut Vec<u8>) {
+        match self {
+            Value::Unit => output.push(Tag::Unit.into()),
+            Value::Bool(false) => output.push(Tag::BoolFalse.into()),
+            Value::Bool(true) => output.push(Tag::BoolTrue.into()),
+            Value::Signed(v) => encode_signed(*v, output),
+            Value::Unsigned(v) => encode_unsigned(*v, output),
+            Value::F32(v) => {
+                output.push(Tag::F32.into());
+                output.extend_from_slice(&v.to_be_bytes());
+            }
+            Value::F64(v) => {
+                output.push(Tag::F64.into());
+                output.extend_from_slice(&v.to_be_bytes());
+            }
+            Value::Char(c) => {
+                let mut buff = [0; 4];
+                let (tag, bytes) = Tag::encode_char(*c, &mut buff);
+                output.push(tag.into());
+                output.extend_from_slice(bytes);
+            }
+            Value::String(v) => encode_bytes(Tag::String, v.as_bytes(), output),
+            Value::Bytes(v) => encode_bytes(Tag::Bytes, v, output),
+            Value::Option(None) => output.push(Tag::None.into()),
+            Value::Option(Some(v)) => {
+                output.push(Tag::Some.into());
+                v.encode(output);
+            }
+            Value::UnitStruct => output.push(Tag::UnitStruct.into()),
+            Value::NewTypeStruct(v) => {
+                output.push(Tag::NewTypeStruct.into());
+                v.encode(output);
+            }
+            Value::Seq(values) => encode_values(Tag::Seq, values, output),
+            Value::Tuple(values) => encode_values(Tag::Tuple, values, output),
+            Value::TupleStruct(values) => encode_values(Tag::TupleStruct, values, output),
+            Value::Map(entries) => {
+                output.push(Tag::Map.into());
+                encode_len(entries.len(), output);
+                for (key, value) in entries {
+                    key.encode(output);
+                    value.encode(output);
+                }
+            }
+            Value::Struct(fields) => encode_fields(Tag::Struct, fields, output),
+            Value::Variant { id, content } => {
+                let tag = match content {
+                    VariantContent::Unit => Tag::UnitVariant,
+                    VariantContent::NewType(_) => Tag::NewTypeVariant,
+                    VariantContent::Tuple(_) => Tag::TupleVariant,
+                    VariantContent::Struct(_) => Tag::StructVariant,
+                };
+                output.push(tag.into());
+                id.encode(output);
+                match content {
+                    VariantContent::Unit => {}
+                    VariantContent::NewType(value) => value.encode(output),
+                    VariantContent::Tuple(values) => {
+                        encode_len(values.len(), output);
+                        values.iter().for_each(|value| value.encode(output));
+                    }
+                    VariantContent::Struct(fields) => {
+                        encode_len(fields.len(), output);
+                        for (name, value) in fields {
+                            encode_bytes(Tag::String, name.as_bytes(), output);
+                            value.encode(output);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.encode(&mut output);
+        output
+    }
+
+    /// Whether both values are the same, unlike `==` floats are compared by their bits so a NaN is the same as itself.
+    pub fn same(&self, other: &Value) -> bool {
+        fn same_values(a: &[Value], b: &[Value]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same(b))
+        }
+        fn same_fields(a: &[(String, Value)], b: &[(String, Value)]) -> bool {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|((a_name, a), (b_name, b))| a_name == b_name && a.same(b))
+        }
+        match (self, other) {
+            (Value::F32(a), Value::F32(b)) => a.to_bits() == b.to_bits(),
+            (Value::F64(a), Value::F64(b)) => a.to_bits() == b.to_bits(),
+            (Value::Option(Some(a)), Value::Option(Some(b)))
+            | (Value::NewTypeStruct(a), Value::NewTypeStruct(b)) => a.same(b),
+            (Value::Seq(a), Value::Seq(b))
+            | (Value::Tuple(a), Value::Tuple(b))
+            | (Value::TupleStruct(a), Value::TupleStruct(b)) => same_values(a, b),
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|((a_key, a), (b_key, b))| a_key.same(b_key) && a.same(b))
+            }
+            (Value::Struct(a), Value::Struct(b)) => same_fields(a, b),
+            (
+                Value::Variant { id, content },
+                Value::Variant {
+                    id: other_id,
+                    content: other_content,
+                },
+            ) => {
+                id.same(other_id)
+                    && match (content, other_content) {
+                        (VariantContent::NewType(a), VariantContent::NewType(b)) => a.same(b),
+                        (VariantContent::Tuple(a), VariantContent::Tuple(b)) => same_values(a, b),
+                        (VariantContent::Struct(a), VariantContent::Struct(b)) => same_fields(a, b),
+                        (a, b) => a == b,
+                    }
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// A value is serialized as a byte array holding its encoding.
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct ValueVisitor;
+
+        impl de::Visitor<'_> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an encoded value")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> core::result::Result<Value, E>
+            where
+                E: de::Error,
+            {
+                Value::from_bytes(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_bytes(ValueVisitor)
+    }
+}
+
+fn encode_unsigned(v: u128, output: &mut Vec<u8>) {
+    if let Ok(v) = u8::try_from(v) {
+        output.extend_from_slice(&[Tag::U8.into(), v]);
+    } else if let Ok(v) = u16::try_from(v) {
+        output.push(Tag::U16.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = u32::try_from(v) {
+        output.push(Tag::U32.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = u64::try_from(v) {
+        output.push(Tag::U64.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else {
+        output.push(Tag::U128.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn encode_signed(v: i128, output: &mut Vec<u8>) {
+    if let Ok(v) = i8::try_from(v) {
+        output.push(Tag::I8.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = i16::try_from(v) {
+        output.push(Tag::I16.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = i32::try_from(v) {
+        output.push(Tag::I32.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else if let Ok(v) = i64::try_from(v) {
+        output.push(Tag::I64.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    } else {
+        output.push(Tag::I128.into());
+        output.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn encode_len(len: usize, output: &mut Vec<u8>) {
+    encode_unsigned(len as u128, output)
+}
+
+fn encode_bytes(tag: Tag, bytes: &[u8], output: &mut Vec<u8>) {
+    output.push(tag.into());
+    encode_len(bytes.len(), output);
+    output.extend_from_slice(bytes);
+}
+
+fn encode_values(tag: Tag, values: &[Value], output: &mut Vec<u8>) {
+    output.push(tag.into());
+    encode_len(values.len(), output);
+    values.iter().for_each(|value| value.encode(output));
+}
+
+fn encode_fields(tag: Tag, fields: &[(String, Value)], output: &mut Vec<u8>) {
+    output.push(tag.into());
+    encode_len(fields.len(), output);
+    for (name, value) in fields {
+        encode_bytes(Tag::String, name.as_bytes(), output);
+        value.encode(output);
+    }
+}
+
+struct Parser<R> {
+    reader: R,
+    // whether a type name was read
+    type_names: bool,
+}
+
+const LEN_TAGS: &[Tag] = &[Tag::U8, Tag::U16, Tag::U32, Tag::U64];
+
+impl<'de, R: Read<'de>> Parser<R> {
+    fn tag(&mut self) -> Result<Tag, R::Error> {
+        let byte = self.reader.read_byte()?;
+        Tag::try_from(byte).map_err(Error::TagParsingError)
+    }
+
+    fn pop_n<const N: usize>(&mut self) -> Result<[u8; N], R::Error> {
+        let mut buff = [0; N];
+        self.reader.read_to_buff(&mut buff)?;
+        Ok(buff)
+    }
+
+    fn unsigned(&mut self, tag: Tag) -> Result<Option<u128>, R::Error> {
+        let v = match tag {
+            Tag::U8 => u8::from_be_bytes(self.pop_n()?).into(),
+            Tag::U16 => u16::from_be_bytes(self.pop_n()?).into(),
+            Tag::U32 => u32::from_be_bytes(self.pop_n()?).into(),
+            Tag::U64 => u64::from_be_bytes(self.pop_n()?).into(),
+            #[cfg(not(no_integer128))]
+            Tag::U128 => u128::from_be_bytes(self.pop_n()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(v))
+    }
+
+    fn signed(&mut self, tag: Tag) -> Result<Option<i128>, R::Error> {
+        let v = match tag {
+            Tag::I8 => i8::from_be_bytes(self.pop_n()?).into(),
+            Tag::I16 => i16::from_be_bytes(self.pop_n()?).into(),
+            Tag::I32 => i32::from_be_bytes(self.pop_n()?).into(),
+            Tag::I64 => i64::from_be_bytes(self.pop_n()?).into(),
+            #[cfg(not(no_integer128))]
+            Tag::I128 => i128::from_be_bytes(self.pop_n()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(v))
+    }
+
+    fn len(&mut self) -> Result<usize, R::Error> {
+        let tag = self.tag()?;
+        let len = match tag {
+            Tag::U8 | Tag::U16 | Tag::U32 | Tag::U64 => self.unsigned(tag)?.unwrap_or_default(),
+            got => {
+                return Err(Error::UnexpectedTag(UnexpectedTag {
+                    expected: LEN_TAGS,
+                    got,
+                }))
+            }
+        };
+        usize::try_from(len).map_err(|_| Error::InvalidLen(len as u64))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, R::Error> {
+        Ok(self.reader.read_bytes(len)?.to_vec())
+    }
+
+    fn string(&mut self, bytes: Vec<u8>) -> Result<String, R::Error> {
+        String::from_utf8(bytes).map_err(|err| Error::Utf8Error(err.utf8_error()))
+    }
+
+    fn chunks(&mut self) -> Result<Vec<u8>, R::Error> {
+        let mut bytes = Vec::new();
+        loop {
+            let tag = self.tag()?;
+            if tag == Tag::UnsizedSeqEnd {
+                return Ok(bytes);
+            }
+            let len = match self.unsigned(tag)? {
+                Some(len) if tag != Tag::U128 => len,
+                _ => {
+                    return Err(Error::UnexpectedTag(UnexpectedTag {
+                        expected: LEN_TAGS,
+                        got: tag,
+                    }))
+                }
+            };
+            let len = usize::try_from(len).map_err(|_| Error::InvalidLen(len as u64))?;
+            bytes.extend_from_slice(&self.reader.read_bytes(len)?);
+        }
+    }
+
+    fn values(&mut self, len: usize) -> Result<Vec<Value>, R::Error> {
+        (0..len)
+            .map(|_| {
+                let tag = self.tag()?;
+                self.value(tag)
+            })
+            .collect()
+    }
+
+    fn unsized_values(&mut self) -> Result<Vec<Value>, R::Error> {
+        let mut values = Vec::new();
+        loop {
+            match self.tag()? {
+                Tag::UnsizedSeqEnd => return Ok(values),
+                tag => values.push(self.value(tag)?),
+            }
+        }
+    }
+
+    fn entries(&mut self, len: Option<usize>) -> Result<Vec<(Value, Value)>, R::Error> {
+        let mut entries = Vec::new();
+        loop {
+            if len == Some(entries.len()) {
+                return Ok(entries);
+            }
+            let key = match self.tag()? {
+                Tag::UnsizedSeqEnd if len.is_none() => return Ok(entries),
+                tag => self.value(tag)?,
+            };
+            let tag = self.tag()?;
+            entries.push((key, self.value(tag)?));
+        }
+    }
+
+    fn fields(&mut self) -> Result<Vec<(String, Value)>, R::Error> {
+        let len = self.len()?;
+        (0..len)
+            .map(|_| {
+                let name = match self.tag()? {
+                    Tag::String => {
+                        let len = self.len()?;
+                        let bytes = self.bytes(len)?;
+                        self.string(bytes)?
+                    }
+                    got => {
+                        return Err(Error::UnexpectedTag(UnexpectedTag {
+                            expected: &[Tag::String],
+                            got,
+                        }))
+                    }
+                };
+                let tag = self.tag()?;
+                Ok((name, self.value(tag)?))
+            })
+            .collect()
+    }
+
+    fn value(&mut self, tag: Tag) -> Result<Value, R::Error> {
+        if let Some(v) = self.unsigned(tag)? {
+            return Ok(Value::Unsigned(v));
+        }
+        if let Some(v) = self.signed(tag)? {
+            return Ok(Value::Signed(v));
+        }
+        let value = match tag {
+            Tag::None => Value::Option(None),
+            Tag::Some => {
+                let tag = self.tag()?;
+                Value::Option(Some(Box::new(self.value(tag)?)))
+            }
+            Tag::BoolFalse => Value::Bool(false),
+            Tag::BoolTrue => Value::Bool(true),
+            Tag::F32 => Value::F32(f32::from_be_bytes(self.pop_n()?)),
+            Tag::F64 => Value::F64(f64::from_be_bytes(self.pop_n()?)),
+            Tag::Char1 | Tag::Char2 | Tag::Char3 | Tag::Char4 => {
+                let len = tag as usize - Tag::Char1 as usize + 1;
+                let bytes = self.reader.read_bytes(len)?;
+                let c = core::str::from_utf8(&bytes)
+                    .map_err(Error::Utf8Error)?
+                    .chars()
+                    .next()
+                    .unwrap_or_default();
+                Value::Char(c)
+            }
+            Tag::String => {
+                let len = self.len()?;
+                let bytes = self.bytes(len)?;
+                Value::String(self.string(bytes)?)
+            }
+            Tag::MarkerTerminatedString => {
+                let bytes = match self.reader.read_bytes_until(&UNSIZED_STRING_END_MARKER)? {
+                    Reference::Borrowed(bytes) => bytes.to_vec(),
+                    Reference::Copied(bytes) => bytes.to_vec(),
+                };
+                // the result ends with the 2 bytes of the marker.
+                let mut bytes = bytes;
+                bytes.truncate(bytes.len() - 2);
+                Value::String(self.string(bytes)?)
+            }
+            Tag::ChunkedString => {
+                let bytes = self.chunks()?;
+                Value::String(self.string(bytes)?)
+            }
+            Tag::Bytes => {
+                let len = self.len()?;
+                Value::Bytes(self.bytes(len)?)
+            }
+            Tag::ChunkedBytes => Value::Bytes(self.chunks()?),
+            Tag::Unit => Value::Unit,
+            Tag::UnitStruct => Value::UnitStruct,
+            Tag::NewTypeStruct => {
+                let tag = self.tag()?;
+                Value::NewTypeStruct(Box::new(self.value(tag)?))
+            }
+            Tag::Seq => {
+                let len = self.len()?;
+                Value::Seq(self.values(len)?)
+            }
+            Tag::UnsizedSeq => Value::Seq(self.unsized_values()?),
+            Tag::Tuple => {
+                let len = self.len()?;
+                Value::Tuple(self.values(len)?)
+            }
+            Tag::TupleStruct => {
+                let len = self.len()?;
+                Value::TupleStruct(self.values(len)?)
+            }
+            Tag::Map => {
+                let len = self.len()?;
+                Value::Map(self.entries(Some(len))?)
+            }
+            Tag::UnsizedMap => Value::Map(self.entries(None)?),
+            Tag::Struct => Value::Struct(self.fields()?),
+            Tag::UnitVariant | Tag::NewTypeVariant | Tag::TupleVariant | Tag::StructVariant => {
+                let id_tag = self.tag()?;
+                let id = Box::new(self.value(id_tag)?);
+                let content = match tag {
+                    Tag::UnitVariant => VariantContent::Unit,
+                    Tag::NewTypeVariant => {
+                        let tag = self.tag()?;
+                        VariantContent::NewType(Box::new(self.value(tag)?))
+                    }
+                    Tag::TupleVariant => {
+                        let len = self.len()?;
+                        VariantContent::Tuple(self.values(len)?)
+                    }
+                    _ => VariantContent::Struct(self.fields()?),
+                };
+                Value::Variant { id, content }
+            }
+            Tag::TypeName => {
+                // the name isn't kept.
+                self.type_names = true;
+                let name_tag = self.tag()?;
+                self.value(name_tag)?;
+                let tag = self.tag()?;
+                self.value(tag)?
+            }
+            got => return Err(Error::UnexpectedTag(UnexpectedTag { expected: &[], got })),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Drawing {
+        name: String,
+        shapes: Vec<Shape>,
+        layers: BTreeMap<u8, (i16, char)>,
+        parent: Option<Box<Drawing>>,
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let drawing = Drawing {
+            name: "sketch".into(),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Rect { w: 70000, h: 3 },
+            ],
+            layers: BTreeMap::from([(1, (-300, 'é')), (2, (4, 'x'))]),
+            parent: Some(Box::new(Drawing {
+                name: "base".into(),
+                shapes: vec![],
+                layers: BTreeMap::new(),
+                parent: None,
+            })),
+        };
+        let bytes = crate::to_bytes(&drawing).unwrap();
+        let value = Value::from_bytes(&bytes).unwrap();
+        let Value::Struct(fields) = &value else {
+            panic!("expected a struct, got {:?}", value);
+        };
+        assert_eq!(fields[0], ("name".into(), Value::String("sketch".into())));
+
+        let encoded = value.to_bytes();
+        let decoded: Drawing = crate::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, drawing);
+    }
+}